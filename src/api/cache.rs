@@ -0,0 +1,152 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Error, anyhow};
+use bytes::Bytes;
+use log::{info, warn};
+use tokio::{fs, task::spawn_blocking};
+
+use crate::{api::net, app::config::cache_dir, parser::atomic};
+
+/// Search results change, covers almost never do
+pub const PAGE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const IMG_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Oldest entries are removed above this size
+const SIZE_LIMIT: u64 = 500 * 1024 * 1024;
+/// Eviction brings cache down to this size to not run it on every insert
+const SIZE_AFTER_EVICT: u64 = SIZE_LIMIT / 10 * 8;
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+/// Approximate size of the cache folder, recalculated on eviction
+static SIZE: AtomicU64 = AtomicU64::new(0);
+static EVICTED_ON_START: AtomicBool = AtomicBool::new(false);
+
+/// Entry file is `url\nbody`, url is checked on read in case of hash collision
+fn entry_path(url: &str) -> Option<PathBuf> {
+    // tests never read or fill the user cache
+    if cfg!(test) {
+        return None;
+    }
+    cache_dir().map(|d| d.join(format!("{:016x}", fnv1a(url.as_bytes()))))
+}
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Urls are looked up without api keys, see `net::redact`
+pub async fn get(url: &str, ttl: Duration) -> Option<Bytes> {
+    let url = &net::redact(url);
+    let res = read(url, ttl).await;
+    let (hits, misses) = if res.is_some() {
+        (HITS.fetch_add(1, Relaxed) + 1, MISSES.load(Relaxed))
+    } else {
+        (HITS.load(Relaxed), MISSES.fetch_add(1, Relaxed) + 1)
+    };
+    let hit = if res.is_some() { "hit" } else { "miss" };
+    info!("cache {hit} ({hits} hits, {misses} misses): {url}");
+    res
+}
+async fn read(url: &str, ttl: Duration) -> Option<Bytes> {
+    let path = entry_path(url)?;
+    let modified = fs::metadata(&path).await.ok()?.modified().ok()?;
+    if modified.elapsed().unwrap_or(Duration::MAX) > ttl {
+        let _ = fs::remove_file(&path).await;
+        return None;
+    }
+    let mut entry = Bytes::from(fs::read(&path).await.ok()?);
+    let split = entry.iter().position(|b| *b == b'\n')?;
+    if &entry[..split] != url.as_bytes() {
+        return None;
+    }
+    Some(entry.split_off(split + 1))
+}
+
+pub async fn put(url: &str, body: &Bytes) {
+    let url = &net::redact(url);
+    let _ = write(url, body)
+        .await
+        .inspect_err(|e| warn!("cache entry was not written {url}: {e}"));
+}
+async fn write(url: &str, body: &Bytes) -> Result<(), Error> {
+    let path = entry_path(url).ok_or(anyhow!("cache dir is unknown"))?;
+    let dir = path.parent().expect("entry has parent").to_path_buf();
+    fs::create_dir_all(&dir).await?;
+
+    if !EVICTED_ON_START.swap(true, Relaxed) {
+        evict_in_background(dir.clone(), SIZE_LIMIT).await;
+    }
+
+    let mut entry = Vec::with_capacity(url.len() + 1 + body.len());
+    entry.extend_from_slice(url.as_bytes());
+    entry.push(b'\n');
+    entry.extend_from_slice(body);
+    let len = entry.len() as u64;
+    // a killed write must not leave a cut body that reads as a hit
+    spawn_blocking(move || atomic::write_bytes(&path, &entry)).await??;
+
+    if SIZE.fetch_add(len, Relaxed) + len > SIZE_LIMIT {
+        evict_in_background(dir, SIZE_AFTER_EVICT).await;
+    }
+    Ok(())
+}
+
+/// Evict on the blocking pool, listing a full cache takes a while
+async fn evict_in_background(dir: PathBuf, limit: u64) {
+    if let Ok(size) = spawn_blocking(move || evict(&dir, limit)).await {
+        SIZE.store(size, Relaxed);
+    }
+}
+
+/// Remove oldest entries until folder fits into `limit`, returns size after
+fn evict(dir: &Path, limit: u64) -> u64 {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = read
+        .filter_map(|e| {
+            let e = e.ok()?;
+            let meta = e.metadata().ok()?;
+            Some((meta.modified().ok()?, meta.len(), e.path()))
+        })
+        .collect();
+    let mut size: u64 = entries.iter().map(|e| e.1).sum();
+    if size <= limit {
+        return size;
+    }
+    entries.sort_by_key(|e| e.0);
+    let mut removed = 0;
+    for (_, len, path) in entries {
+        if size <= limit {
+            break;
+        }
+        if std::fs::remove_file(path).is_ok() {
+            size -= len;
+            removed += 1;
+        }
+    }
+    info!("cache evicted {removed} entries, {size} bytes left");
+    size
+}
+
+pub async fn clear() -> Result<(), Error> {
+    let dir = cache_dir().ok_or(anyhow!("cache dir is unknown"))?;
+    if fs::try_exists(&dir).await? {
+        fs::remove_dir_all(&dir).await?;
+    }
+    SIZE.store(0, Relaxed);
+    info!(
+        "cache cleared ({} hits, {} misses this session)",
+        HITS.load(Relaxed),
+        MISSES.load(Relaxed)
+    );
+    Ok(())
+}
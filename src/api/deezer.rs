@@ -0,0 +1,271 @@
+use anyhow::Error;
+use iced::futures::channel::mpsc::Sender;
+use log::{info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{
+    api::{
+        net,
+        queue::{
+            Source::{self, *},
+            TagsInput,
+        },
+        registry::ApiKeys,
+        shared::{self, WebSource, send_song},
+    },
+    app::{
+        iced_app::Message,
+        img::{ImageProgress, ImgFormat, SongImg},
+    },
+};
+
+const API_URL: &str = "https://api.deezer.com/";
+const SEARCH_LIMIT: i32 = 5;
+/// cdn serves any square size up to 1800, `cover_xl` is 1000
+const SIZES: [u32; 4] = [1800, 1400, 1000, 500];
+
+#[derive(Deserialize)]
+struct DzArtist {
+    name: String,
+}
+/// Album of /search/album, or nested album of /search/track without artist and link
+#[derive(Deserialize)]
+struct DzAlbum {
+    title: String,
+    link: Option<String>,
+    artist: Option<DzArtist>,
+    cover_medium: Option<String>,
+    cover_xl: Option<String>,
+}
+#[derive(Deserialize)]
+struct DzTrack {
+    artist: DzArtist,
+    album: DzAlbum,
+}
+/// Errors come back with status 200 and no `data`
+#[derive(Deserialize)]
+struct DzSearch<T> {
+    #[serde(default = "Vec::new")]
+    data: Vec<T>,
+}
+
+pub struct Deezer {
+    tags: TagsInput,
+    tx: Sender<Message>,
+    client: Client,
+    api_url: String,
+}
+
+impl WebSource for Deezer {
+    fn build_title_pompt(&self, title: &str, artist: &str) -> String {
+        format!("artist:\"{}\" track:\"{}\"", artist, title)
+    }
+    fn build_album_pompt(&self, album: &str, artist: &str) -> String {
+        format!("artist:\"{}\" album:\"{}\"", artist, album)
+    }
+    const ALBUM_SOURCE: Source = DeezerAlbum;
+    const TITLE_SOURCE: Source = DeezerTitle;
+
+    fn tags_ref(&self) -> &TagsInput {
+        &self.tags
+    }
+
+    fn tx_ref(&self) -> &Sender<Message> {
+        &self.tx
+    }
+    fn tx_clone(&self) -> Sender<Message> {
+        self.tx.clone()
+    }
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
+        let this = Self {
+            tags,
+            tx,
+            client: net::client(),
+            api_url: API_URL.to_string(),
+        };
+        shared::init_source(this).await?;
+        Ok(())
+    }
+
+    async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error> {
+        let endpoint = if src == DeezerAlbum { "album" } else { "track" };
+        let search_url = format!(
+            "{}search/{}?q={}&limit={}",
+            self.api_url,
+            endpoint,
+            urlencoding::encode(prompt),
+            SEARCH_LIMIT
+        );
+        info!("Fetching search: {}", search_url);
+
+        let body = shared::get_page(self.client.get(&search_url)).await?;
+        let albums: Vec<(DzAlbum, String)> = if src == DeezerAlbum {
+            serde_json::from_str::<DzSearch<DzAlbum>>(&body)?
+                .data
+                .into_iter()
+                .map(|album| {
+                    let artist = album.artist.as_ref().map(|a| a.name.clone());
+                    (album, artist.unwrap_or_default())
+                })
+                .collect()
+        } else {
+            serde_json::from_str::<DzSearch<DzTrack>>(&body)?
+                .data
+                .into_iter()
+                .map(|track| (track.album, track.artist.name))
+                .collect()
+        };
+
+        // several tracks of one album share a cover
+        let mut seen = Vec::new();
+        for (album, artist) in albums {
+            let Some(cover_xl) = album.cover_xl.clone() else {
+                continue;
+            };
+            if seen.contains(&cover_xl) {
+                continue;
+            }
+            seen.push(cover_xl.clone());
+            info!("Found result: {} {}", album.title, cover_xl);
+
+            let mut feedback = format!("album: {}\nartist: {}", album.title, artist);
+            if let Some(link) = &album.link {
+                feedback.push_str("\nurl: ");
+                feedback.push_str(link);
+            }
+            let thumb = album.cover_medium.unwrap_or(cover_xl.clone());
+            let _ = self
+                .fetch_and_send_artwork(thumb, cover_xl, feedback, src)
+                .await;
+        }
+        info!("Found {} matches in search", seen.len());
+        Ok(())
+    }
+}
+impl Deezer {
+    async fn fetch_and_send_artwork(
+        &self,
+        img_small: String,
+        cover_xl: String,
+        feedback: String,
+        src: Source,
+    ) -> Result<(), Error> {
+        let mut url_patterns: Vec<String> = SIZES
+            .iter()
+            .filter_map(|size| Self::sized_url(&cover_xl, *size))
+            .collect();
+        if url_patterns.is_empty() {
+            url_patterns.push(cover_xl);
+        }
+
+        let thumbnail = shared::get_img(&self.client, vec![img_small.clone()])
+            .await
+            .inspect_err(|e| {
+                warn!("image thumbnail could not download {img_small}, {e}");
+            })?;
+
+        let new_img = SongImg::new(
+            ImgFormat::Jpeg,
+            ImageProgress::RawPreview(url_patterns, thumbnail),
+            src,
+            feedback,
+        );
+        send_song(self, new_img).await;
+
+        Ok(())
+    }
+    /// Replace size in file name:
+    /// .../cover/{hash}/1000x1000-000000-80-0-0.jpg -> .../cover/{hash}/1800x1800-000000-80-0-0.jpg
+    fn sized_url(url: &str, size: u32) -> Option<String> {
+        let (base, file) = url.rsplit_once('/')?;
+        let (dims, rest) = file.split_once('-')?;
+        if !dims.contains('x') {
+            return None;
+        }
+        Some(format!("{base}/{size}x{size}-{rest}"))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use crate::{
+        api::{
+            deezer::Deezer,
+            mock::{Route, source_test, tags},
+            queue::Source,
+        },
+        app::img::{ImageProgress, SongImg},
+    };
+
+    const ALBUM: &str = include_str!("../../resources/fixtures/deezer/search_album.json");
+    const TRACK: &str = include_str!("../../resources/fixtures/deezer/search_track.json");
+    const ERROR: &str = include_str!("../../resources/fixtures/deezer/error.json");
+
+    fn run(album: &str, track: &str) -> (Vec<SongImg>, String) {
+        let (imgs, _, url) = source_test(
+            |server| {
+                vec![
+                    Route::json("/search/album", &server.fixture(album)),
+                    Route::json("/search/track", &server.fixture(track)),
+                    Route::jpeg("/images/cover/"),
+                ]
+            },
+            |tx, url| Deezer {
+                tags: tags("Daft Punk", "Get Lucky", "Random Access Memories"),
+                tx,
+                client: Client::new(),
+                api_url: format!("{url}/"),
+            },
+        );
+        (imgs, url)
+    }
+
+    #[test]
+    fn album_and_track() {
+        let (imgs, url) = run(ALBUM, TRACK);
+        // both tracks of the second search are on the same album
+        assert_eq!(imgs.len(), 3);
+        assert_eq!(imgs[0].src, Source::DeezerAlbum);
+        assert_eq!(imgs[2].src, Source::DeezerTitle);
+        let ImageProgress::RawPreview(urls, _) = &imgs[0].image else {
+            panic!("deezer sends previews");
+        };
+        let cover = format!("{url}/images/cover/311bba0fc112d15f72c8b5a65f0456c1");
+        assert_eq!(
+            urls,
+            &vec![
+                format!("{cover}/1800x1800-000000-80-0-0.jpg"),
+                format!("{cover}/1400x1400-000000-80-0-0.jpg"),
+                format!("{cover}/1000x1000-000000-80-0-0.jpg"),
+                format!("{cover}/500x500-000000-80-0-0.jpg"),
+            ]
+        );
+        assert!(imgs[2].feedback.contains("artist: Daft Punk"));
+        // probed from the header of the first url that answered
+        assert!(imgs.iter().all(|img| img.orig_res.is_some()));
+    }
+    #[test]
+    fn error_response() {
+        let (imgs, _) = run(ERROR, ERROR);
+        assert!(imgs.is_empty());
+    }
+    #[test]
+    fn sized_url() {
+        assert_eq!(
+            Deezer::sized_url(
+                "https://cdn/images/cover/a/1000x1000-000000-80-0-0.jpg",
+                500
+            ),
+            Some("https://cdn/images/cover/a/500x500-000000-80-0-0.jpg".to_string())
+        );
+        assert_eq!(
+            Deezer::sized_url("https://cdn/images/cover/a.jpg", 500),
+            None
+        );
+    }
+}
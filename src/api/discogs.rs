@@ -0,0 +1,350 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, bail};
+use bytes::Bytes;
+use iced::futures::channel::mpsc::Sender;
+use log::{info, warn};
+use reqwest::{Client, StatusCode, header::HeaderMap};
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use crate::{
+    api::{
+        cache, net,
+        queue::{
+            Source::{self, *},
+            TagsInput,
+        },
+        registry::ApiKeys,
+        shared::{self, WebSource, send_song},
+    },
+    app::{
+        iced_app::Message,
+        img::{ImageProgress, ImgFormat, SongImg},
+    },
+};
+
+const API_URL: &str = "https://api.discogs.com/";
+/// Every result costs one more request for its images
+const SEARCH_LIMIT: usize = 3;
+/// Discogs counts requests in a moving minute
+const LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Requests are held until this moment, shared by all songs
+static BLOCKED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Deserialize)]
+struct DcSearchResult {
+    id: u64,
+}
+#[derive(Deserialize)]
+struct DcSearch {
+    #[serde(default)]
+    results: Vec<DcSearchResult>,
+}
+#[derive(Deserialize)]
+struct DcImage {
+    #[serde(rename = "type")]
+    kind: String,
+    uri: String,
+    uri150: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+#[derive(Deserialize)]
+struct DcLabel {
+    name: String,
+    catno: Option<String>,
+}
+#[derive(Deserialize)]
+struct DcRelease {
+    title: String,
+    artists_sort: Option<String>,
+    year: Option<u32>,
+    #[serde(default)]
+    labels: Vec<DcLabel>,
+    #[serde(default)]
+    images: Vec<DcImage>,
+    uri: Option<String>,
+}
+
+pub struct Discogs {
+    tags: TagsInput,
+    tx: Sender<Message>,
+    client: Client,
+    token: String,
+    api_url: String,
+}
+
+impl WebSource for Discogs {
+    fn build_title_pompt(&self, title: &str, artist: &str) -> String {
+        format!(
+            "artist={}&track={}",
+            urlencoding::encode(artist),
+            urlencoding::encode(title)
+        )
+    }
+    fn build_album_pompt(&self, album: &str, artist: &str) -> String {
+        format!(
+            "artist={}&release_title={}",
+            urlencoding::encode(artist),
+            urlencoding::encode(album)
+        )
+    }
+    const ALBUM_SOURCE: Source = DiscogsAlbum;
+    const TITLE_SOURCE: Source = DiscogsTitle;
+
+    fn tags_ref(&self) -> &TagsInput {
+        &self.tags
+    }
+
+    fn tx_ref(&self) -> &Sender<Message> {
+        &self.tx
+    }
+    fn tx_clone(&self) -> Sender<Message> {
+        self.tx.clone()
+    }
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, keys: ApiKeys) -> Result<(), Error> {
+        if keys.discogs.trim().is_empty() {
+            bail!("discogs token is not set");
+        }
+        let this = Self {
+            tags,
+            tx,
+            client: net::client(),
+            token: keys.discogs.trim().to_string(),
+            api_url: API_URL.to_string(),
+        };
+        shared::init_source(this).await?;
+        Ok(())
+    }
+
+    async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error> {
+        let search_url = format!(
+            "{}database/search?type=release&{}&per_page={}",
+            self.api_url, prompt, SEARCH_LIMIT
+        );
+        info!("Fetching search: {}", search_url);
+
+        let body = self.get_api(&search_url).await?;
+        let search: DcSearch = serde_json::from_slice(&body)?;
+
+        for res in search.results.iter().take(SEARCH_LIMIT) {
+            let release_url = format!("{}releases/{}", self.api_url, res.id);
+            let release =
+                match self.get_api(&release_url).await.and_then(|body| {
+                    serde_json::from_slice::<DcRelease>(&body).map_err(Error::from)
+                }) {
+                    Ok(release) => release,
+                    Err(e) => {
+                        warn!("discogs release {} was not loaded: {e}", res.id);
+                        continue;
+                    }
+                };
+            info!("Found result: {} {}", release.title, release_url);
+            self.fetch_and_send_artwork(release, src).await;
+        }
+        info!("Found {} matches in search", search.results.len());
+        Ok(())
+    }
+}
+impl Discogs {
+    async fn fetch_and_send_artwork(&self, release: DcRelease, src: Source) {
+        let mut feedback = format!("album: {}", release.title);
+        if let Some(artist) = &release.artists_sort {
+            feedback.push_str(&format!("\nartist: {artist}"));
+        }
+        if let Some(year) = release.year.filter(|y| *y != 0) {
+            feedback.push_str(&format!("\nyear: {year}"));
+        }
+        if let Some(label) = release.labels.first() {
+            feedback.push_str(&format!("\nlabel: {}", label.name));
+            if let Some(catno) = &label.catno {
+                feedback.push_str(&format!("\ncatalog number: {catno}"));
+            }
+        }
+        if let Some(uri) = &release.uri {
+            feedback.push_str(&format!("\nurl: {uri}"));
+        }
+
+        // front cover first, then back, inlay and labels in release order
+        let (primary, secondary): (Vec<_>, Vec<_>) =
+            release.images.iter().partition(|img| img.kind == "primary");
+        for img in primary.into_iter().chain(secondary) {
+            let thumb_url = img.uri150.clone().unwrap_or(img.uri.clone());
+            let Ok(thumbnail) = shared::get_img(&self.client, vec![thumb_url.clone()])
+                .await
+                .inspect_err(|e| warn!("image thumbnail could not download {thumb_url}, {e}"))
+            else {
+                continue;
+            };
+            let mut img_feedback = format!("{feedback}\nimage: {}", img.kind);
+            if let (Some(w), Some(h)) = (img.width, img.height) {
+                img_feedback.push_str(&format!(" {w}x{h}"));
+            }
+            let mut new_img = SongImg::new(
+                ImgFormat::from_url(&img.uri),
+                ImageProgress::RawPreview(vec![img.uri.clone()], thumbnail),
+                src,
+                img_feedback,
+            );
+            // the api knows the size, no probe needed
+            new_img.orig_res = img.width.zip(img.height);
+            send_song(self, new_img).await;
+        }
+    }
+    /// Authenticated api request, cached by url and held back by the rate limit
+    async fn get_api(&self, url: &str) -> Result<Bytes, Error> {
+        if let Some(body) = cache::get(url, cache::PAGE_TTL).await {
+            return Ok(body);
+        }
+        // another song may have pushed the block further while this one slept
+        while let Some(wait) = Self::blocked_for() {
+            info!("discogs rate limit, waiting {}s", wait.as_secs());
+            sleep(wait).await;
+        }
+
+        let (response, _permit) = net::send(
+            self.client
+                .get(url)
+                .header("Authorization", format!("Discogs token={}", self.token)),
+        )
+        .await?;
+        let status = response.status();
+        if let Some(wait) = Self::limit_wait(status, response.headers()) {
+            *BLOCKED_UNTIL.lock().unwrap() = Some(Instant::now() + wait);
+        }
+        if !status.is_success() {
+            bail!("discogs responded {status} for {url}");
+        }
+        let body = response.bytes().await?;
+        cache::put(url, &body).await;
+        Ok(body)
+    }
+    fn blocked_for() -> Option<Duration> {
+        let until = (*BLOCKED_UNTIL.lock().unwrap())?;
+        until
+            .checked_duration_since(Instant::now())
+            .filter(|wait| !wait.is_zero())
+    }
+    /// Pause needed before the next request, from `X-Discogs-Ratelimit-Remaining`
+    fn limit_wait(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Some(LIMIT_WINDOW);
+        }
+        let remaining: u32 = headers
+            .get("X-Discogs-Ratelimit-Remaining")?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()?;
+        // other songs are querying at the same time, keep one spare
+        (remaining <= 1).then_some(LIMIT_WINDOW)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use reqwest::{
+        StatusCode,
+        header::{HeaderMap, HeaderValue},
+    };
+
+    use crate::{
+        api::{
+            discogs::{Discogs, LIMIT_WINDOW},
+            mock::{Route, source_test, tags},
+            net,
+            queue::Source,
+        },
+        app::img::{ImageProgress, SongImg},
+    };
+
+    const SEARCH: &str = include_str!("../../resources/fixtures/discogs/search.json");
+    const RELEASE: &str = include_str!("../../resources/fixtures/discogs/release.json");
+    const EMPTY: &str = include_str!("../../resources/fixtures/discogs/empty.json");
+
+    fn run(search: &str) -> (Vec<SongImg>, Vec<String>) {
+        let (imgs, requests, _) = source_test(
+            |server| {
+                let mut release = Route::json("/releases/1061640", &server.fixture(RELEASE));
+                release
+                    .headers
+                    .push(("X-Discogs-Ratelimit-Remaining", "58".to_string()));
+                vec![
+                    Route::json("release_title=", &server.fixture(search)),
+                    Route::json("track=", &server.fixture(EMPTY)),
+                    release,
+                    Route::jpeg("/images/"),
+                ]
+            },
+            |tx, url| Discogs {
+                tags: tags(
+                    "Boards of Canada",
+                    "Roygbiv",
+                    "Music Has The Right To Children",
+                ),
+                tx,
+                client: net::client(),
+                token: "test_token".to_string(),
+                api_url: format!("{url}/"),
+            },
+        );
+        (imgs, requests)
+    }
+
+    #[test]
+    fn primary_then_secondary() {
+        let (imgs, requests) = run(SEARCH);
+        assert_eq!(imgs.len(), 3);
+        assert!(imgs.iter().all(|img| img.src == Source::DiscogsAlbum));
+        assert!(imgs[0].feedback.contains("image: primary 600x600"));
+        assert_eq!(imgs[0].orig_res, Some((600, 600)));
+        assert!(imgs[1].feedback.contains("image: secondary"));
+        let feedback = &imgs[0].feedback;
+        assert!(feedback.contains("year: 1998"));
+        assert!(feedback.contains("label: Warp Records"));
+        assert!(feedback.contains("catalog number: WARPLP55"));
+        let ImageProgress::RawPreview(urls, _) = &imgs[0].image else {
+            panic!("discogs sends previews");
+        };
+        assert!(urls[0].ends_with("/images/primary-600.jpeg"));
+        assert!(
+            requests
+                .iter()
+                .any(|r| r.contains("artist=Boards%20of%20Canada"))
+        );
+    }
+    #[test]
+    fn no_results() {
+        let (imgs, requests) = run(EMPTY);
+        assert!(imgs.is_empty());
+        assert!(!requests.iter().any(|r| r.contains("/releases/")));
+    }
+    #[test]
+    fn limit_wait() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Discogs::limit_wait(StatusCode::OK, &headers), None);
+        headers.insert(
+            "X-Discogs-Ratelimit-Remaining",
+            HeaderValue::from_static("30"),
+        );
+        assert_eq!(Discogs::limit_wait(StatusCode::OK, &headers), None);
+        headers.insert(
+            "X-Discogs-Ratelimit-Remaining",
+            HeaderValue::from_static("1"),
+        );
+        assert_eq!(
+            Discogs::limit_wait(StatusCode::OK, &headers),
+            Some(LIMIT_WINDOW)
+        );
+        assert_eq!(
+            Discogs::limit_wait(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()),
+            Some(LIMIT_WINDOW)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::Error;
+use iced::futures::channel::mpsc::Sender;
+use log::{info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{
+    api::{
+        net,
+        queue::{
+            Source::{self, *},
+            TagsInput,
+        },
+        registry::ApiKeys,
+        shared::{self, WebSource, send_song},
+    },
+    app::{
+        iced_app::Message,
+        img::{ImageProgress, ImgFormat, SongImg},
+    },
+};
+
+const API_URL: &str = "https://itunes.apple.com/";
+const SEARCH_LIMIT: i32 = 5;
+/// Artwork server scales to any size, 3000 is usually the uploaded file
+const SIZES: [u32; 4] = [3000, 1400, 1000, 600];
+const THUMB_SIZE: u32 = 200;
+
+/// Same fields for `entity=album` and `entity=song`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItResult {
+    artist_name: String,
+    collection_name: Option<String>,
+    collection_view_url: Option<String>,
+    artwork_url100: Option<String>,
+    release_date: Option<String>,
+}
+#[derive(Deserialize)]
+struct ItSearch {
+    results: Vec<ItResult>,
+}
+
+pub struct Itunes {
+    tags: TagsInput,
+    tx: Sender<Message>,
+    client: Client,
+    api_url: String,
+}
+
+impl WebSource for Itunes {
+    fn build_title_pompt(&self, title: &str, artist: &str) -> String {
+        format!("{} {}", artist, title)
+    }
+    fn build_album_pompt(&self, album: &str, artist: &str) -> String {
+        format!("{} {}", artist, album)
+    }
+    const ALBUM_SOURCE: Source = ItunesAlbum;
+    const TITLE_SOURCE: Source = ItunesTitle;
+
+    fn tags_ref(&self) -> &TagsInput {
+        &self.tags
+    }
+
+    fn tx_ref(&self) -> &Sender<Message> {
+        &self.tx
+    }
+    fn tx_clone(&self) -> Sender<Message> {
+        self.tx.clone()
+    }
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
+        let this = Self {
+            tags,
+            tx,
+            client: net::client(),
+            api_url: API_URL.to_string(),
+        };
+        shared::init_source(this).await?;
+        Ok(())
+    }
+
+    async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error> {
+        let entity = if src == ItunesAlbum { "album" } else { "song" };
+        let search_url = format!(
+            "{}search?term={}&media=music&entity={}&limit={}",
+            self.api_url,
+            urlencoding::encode(prompt),
+            entity,
+            SEARCH_LIMIT
+        );
+        info!("Fetching search: {}", search_url);
+
+        let body = shared::get_page(self.client.get(&search_url)).await?;
+        let search: ItSearch = serde_json::from_str(&body)?;
+
+        // several songs of one album share artwork
+        let mut seen = Vec::new();
+        for res in search.results {
+            let Some(artwork) = res.artwork_url100 else {
+                continue;
+            };
+            if seen.contains(&artwork) {
+                continue;
+            }
+            seen.push(artwork.clone());
+            let album = res.collection_name.unwrap_or_default();
+            info!("Found result: {} {}", album, artwork);
+
+            let mut feedback = format!("album: {}\nartist: {}", album, res.artist_name);
+            if let Some(date) = &res.release_date {
+                feedback.push_str("\nreleased: ");
+                feedback.push_str(date.split('T').next().unwrap_or(date));
+            }
+            if let Some(url) = &res.collection_view_url {
+                feedback.push_str("\nurl: ");
+                feedback.push_str(url);
+            }
+            let _ = self.fetch_and_send_artwork(artwork, feedback, src).await;
+        }
+        info!("Found {} matches in search", seen.len());
+        Ok(())
+    }
+}
+impl Itunes {
+    async fn fetch_and_send_artwork(
+        &self,
+        artwork: String,
+        feedback: String,
+        src: Source,
+    ) -> Result<(), Error> {
+        let url_patterns: Vec<String> = SIZES
+            .iter()
+            .filter_map(|size| Self::sized_url(&artwork, *size))
+            .collect();
+        let img_small = Self::sized_url(&artwork, THUMB_SIZE).unwrap_or(artwork);
+        let Some(full) = url_patterns.first() else {
+            warn!("unknown artwork url {img_small}");
+            return Ok(());
+        };
+        let format = ImgFormat::from_url(full);
+
+        let thumbnail = shared::get_img(&self.client, vec![img_small.clone()])
+            .await
+            .inspect_err(|e| {
+                warn!("image thumbnail could not download {img_small}, {e}");
+            })?;
+
+        let new_img = SongImg::new(
+            format,
+            ImageProgress::RawPreview(url_patterns, thumbnail),
+            src,
+            feedback,
+        );
+        send_song(self, new_img).await;
+
+        Ok(())
+    }
+    /// Replace the last path segment:
+    /// .../886443919266.jpg/100x100bb.jpg -> .../886443919266.jpg/3000x3000bb.jpg
+    fn sized_url(url: &str, size: u32) -> Option<String> {
+        let (base, file) = url.rsplit_once('/')?;
+        let (dims, ext) = file.rsplit_once('.')?;
+        if !dims.ends_with("bb") || !dims.contains('x') {
+            return None;
+        }
+        Some(format!("{base}/{size}x{size}bb.{ext}"))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use crate::{
+        api::{
+            itunes::Itunes,
+            mock::{Route, source_test, tags},
+            queue::Source,
+        },
+        app::img::{ImageProgress, SongImg},
+    };
+
+    const ALBUM: &str = include_str!("../../resources/fixtures/itunes/search_album.json");
+    const SONG: &str = include_str!("../../resources/fixtures/itunes/search_song.json");
+    const EMPTY: &str = include_str!("../../resources/fixtures/itunes/empty.json");
+
+    fn run(album: &str, song: &str) -> (Vec<SongImg>, Vec<String>, String) {
+        source_test(
+            |server| {
+                vec![
+                    Route::json("entity=album", &server.fixture(album)),
+                    Route::json("entity=song", &server.fixture(song)),
+                    Route::jpeg("/200x200bb.jpg"),
+                ]
+            },
+            |tx, url| Itunes {
+                tags: tags("Daft Punk", "Get Lucky", "Random Access Memories"),
+                tx,
+                client: Client::new(),
+                api_url: format!("{url}/"),
+            },
+        )
+    }
+
+    #[test]
+    fn album_and_song() {
+        let (imgs, requests, url) = run(ALBUM, SONG);
+        // both songs share the artwork of the first album
+        assert_eq!(imgs.len(), 3);
+        assert_eq!(imgs[0].src, Source::ItunesAlbum);
+        assert_eq!(imgs[2].src, Source::ItunesTitle);
+        let ImageProgress::RawPreview(urls, _) = &imgs[0].image else {
+            panic!("itunes sends previews");
+        };
+        let art = format!(
+            "{url}/image/thumb/Music115/v4/e8/43/5f/e8435ffa-b6b9-b171-40ab-4ff3959ab661/886443919266.jpg"
+        );
+        assert_eq!(
+            urls,
+            &vec![
+                format!("{art}/3000x3000bb.jpg"),
+                format!("{art}/1400x1400bb.jpg"),
+                format!("{art}/1000x1000bb.jpg"),
+                format!("{art}/600x600bb.jpg"),
+            ]
+        );
+        assert!(imgs[0].feedback.contains("released: 2013-05-17"));
+        assert!(requests.iter().any(|r| r.contains("/200x200bb.jpg")));
+    }
+    #[test]
+    fn no_results() {
+        let (imgs, _, _) = run(EMPTY, EMPTY);
+        assert!(imgs.is_empty());
+    }
+    #[test]
+    fn sized_url() {
+        assert_eq!(
+            Itunes::sized_url("https://is1.mzstatic.com/a/b.jpg/100x100bb.jpg", 600),
+            Some("https://is1.mzstatic.com/a/b.jpg/600x600bb.jpg".to_string())
+        );
+        assert_eq!(
+            Itunes::sized_url("https://is1.mzstatic.com/a/b.jpg", 600),
+            None
+        );
+    }
+}
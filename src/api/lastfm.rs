@@ -0,0 +1,259 @@
+use anyhow::{Error, bail};
+use iced::futures::channel::mpsc::Sender;
+use log::{info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{
+    api::{
+        net,
+        queue::{
+            Source::{self, *},
+            TagsInput,
+        },
+        registry::ApiKeys,
+        shared::{self, WebSource, send_song},
+    },
+    app::{
+        iced_app::Message,
+        img::{ImageProgress, ImgFormat, SongImg},
+    },
+};
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+/// Grey star last.fm returns instead of missing art
+const NO_IMAGE: &str = "2a96cbd8b46e442fc41c2b86b821562f";
+
+#[derive(Deserialize)]
+struct LfImage {
+    #[serde(rename = "#text")]
+    url: String,
+    size: String,
+}
+/// `album` of album.getInfo and `track.album` of track.getInfo
+#[derive(Deserialize)]
+struct LfAlbum {
+    #[serde(alias = "title")]
+    name: String,
+    artist: String,
+    url: Option<String>,
+    #[serde(default)]
+    image: Vec<LfImage>,
+}
+#[derive(Deserialize)]
+struct LfTrack {
+    album: Option<LfAlbum>,
+}
+#[derive(Deserialize)]
+struct LfResponse {
+    album: Option<LfAlbum>,
+    track: Option<LfTrack>,
+    message: Option<String>,
+}
+
+pub struct LastFm {
+    tags: TagsInput,
+    tx: Sender<Message>,
+    client: Client,
+    key: String,
+    api_url: String,
+}
+
+impl WebSource for LastFm {
+    fn build_title_pompt(&self, title: &str, artist: &str) -> String {
+        format!(
+            "method=track.getInfo&artist={}&track={}",
+            urlencoding::encode(artist),
+            urlencoding::encode(title)
+        )
+    }
+    fn build_album_pompt(&self, album: &str, artist: &str) -> String {
+        format!(
+            "method=album.getInfo&artist={}&album={}",
+            urlencoding::encode(artist),
+            urlencoding::encode(album)
+        )
+    }
+    const ALBUM_SOURCE: Source = LastFmAlbum;
+    const TITLE_SOURCE: Source = LastFmTitle;
+
+    fn tags_ref(&self) -> &TagsInput {
+        &self.tags
+    }
+
+    fn tx_ref(&self) -> &Sender<Message> {
+        &self.tx
+    }
+    fn tx_clone(&self) -> Sender<Message> {
+        self.tx.clone()
+    }
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, keys: ApiKeys) -> Result<(), Error> {
+        if keys.lastfm.trim().is_empty() {
+            bail!("last.fm api key is not set");
+        }
+        let this = Self {
+            tags,
+            tx,
+            client: net::client(),
+            key: keys.lastfm.trim().to_string(),
+            api_url: API_URL.to_string(),
+        };
+        shared::init_source(this).await?;
+        Ok(())
+    }
+
+    async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error> {
+        let url = format!(
+            "{}?{}&autocorrect=1&format=json&api_key={}",
+            self.api_url, prompt, self.key
+        );
+        info!("Fetching last.fm: {}", prompt);
+
+        let body = shared::get_page(self.client.get(&url)).await?;
+        let res: LfResponse = serde_json::from_str(&body)?;
+
+        let album = res.album.or(res.track.and_then(|t| t.album));
+        let Some(album) = album else {
+            bail!(
+                "last.fm: {}",
+                res.message.unwrap_or("no album in response".to_string())
+            );
+        };
+        self.fetch_and_send_artwork(album, src).await
+    }
+}
+impl LastFm {
+    async fn fetch_and_send_artwork(&self, album: LfAlbum, src: Source) -> Result<(), Error> {
+        let by_size = |size: &str| {
+            album
+                .image
+                .iter()
+                .find(|img| img.size == size && !img.url.is_empty() && !img.url.contains(NO_IMAGE))
+                .map(|img| img.url.clone())
+        };
+        let mut url_patterns = Vec::new();
+        for size in ["mega", "extralarge"] {
+            if let Some(url) = by_size(size)
+                && !url_patterns.contains(&url)
+            {
+                url_patterns.push(url);
+            }
+        }
+        let Some(largest) = url_patterns.first() else {
+            info!("no art on last.fm for {}", album.name);
+            return Ok(());
+        };
+        if let Some(original) = Self::original_url(largest) {
+            url_patterns.insert(0, original);
+        }
+        let thumb_url = by_size("large").unwrap_or(url_patterns.last().unwrap().clone());
+
+        let thumbnail = shared::get_img(&self.client, vec![thumb_url.clone()])
+            .await
+            .inspect_err(|e| {
+                warn!("image thumbnail could not download {thumb_url}, {e}");
+            })?;
+
+        let mut feedback = format!("album: {}\nartist: {}", album.name, album.artist);
+        if let Some(url) = &album.url {
+            feedback.push_str("\nurl: ");
+            feedback.push_str(url);
+        }
+        let new_img = SongImg::new(
+            ImgFormat::from_url(&url_patterns[0]),
+            ImageProgress::RawPreview(url_patterns, thumbnail),
+            src,
+            feedback,
+        );
+        send_song(self, new_img).await;
+        Ok(())
+    }
+    /// Drop size segment to get uploaded file:
+    /// https://lastfm.freetls.fastly.net/i/u/300x300/a.png -> https://lastfm.freetls.fastly.net/i/u/a.png
+    fn original_url(url: &str) -> Option<String> {
+        let (base, rest) = url.split_once("/i/u/")?;
+        let (_, file) = rest.split_once('/')?;
+        Some(format!("{base}/i/u/{file}"))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use crate::{
+        api::{
+            lastfm::LastFm,
+            mock::{Route, source_test, tags},
+            queue::Source,
+        },
+        app::img::{ImageProgress, SongImg},
+    };
+
+    const ALBUM: &str = include_str!("../../resources/fixtures/lastfm/album_getinfo.json");
+    const TRACK: &str = include_str!("../../resources/fixtures/lastfm/track_getinfo.json");
+    const NOT_FOUND: &str = include_str!("../../resources/fixtures/lastfm/not_found.json");
+    const NO_IMAGE: &str = include_str!("../../resources/fixtures/lastfm/no_image.json");
+
+    fn run(album: &str, track: &str) -> (Vec<SongImg>, Vec<String>, String) {
+        source_test(
+            |server| {
+                vec![
+                    Route::json("method=album.getInfo", &server.fixture(album)),
+                    Route::json("method=track.getInfo", &server.fixture(track)),
+                    Route::jpeg("/i/u/174s/"),
+                ]
+            },
+            |tx, url| LastFm {
+                tags: tags("Cher", "Believe", "Believe"),
+                tx,
+                client: Client::new(),
+                key: "test_key".to_string(),
+                api_url: format!("{url}/2.0/"),
+            },
+        )
+    }
+
+    #[test]
+    fn album_and_track() {
+        let (imgs, requests, url) = run(ALBUM, TRACK);
+        assert_eq!(imgs.len(), 2);
+        assert_eq!(imgs[0].src, Source::LastFmAlbum);
+        assert_eq!(imgs[1].src, Source::LastFmTitle);
+        for img in &imgs {
+            let ImageProgress::RawPreview(urls, _) = &img.image else {
+                panic!("last.fm sends previews");
+            };
+            assert_eq!(
+                urls,
+                &vec![
+                    format!("{url}/i/u/3b54885952161aaea4ce2965b2db1638.png"),
+                    format!("{url}/i/u/300x300/3b54885952161aaea4ce2965b2db1638.png"),
+                ]
+            );
+        }
+        assert!(
+            imgs[0]
+                .feedback
+                .contains("https://www.last.fm/music/Cher/Believe")
+        );
+        let api_calls: Vec<_> = requests.iter().filter(|r| r.contains("method=")).collect();
+        assert_eq!(api_calls.len(), 2);
+        assert!(api_calls.iter().all(|r| r.contains("api_key=test_key")));
+    }
+    #[test]
+    fn not_found_and_placeholder() {
+        let (imgs, _, _) = run(NO_IMAGE, NOT_FOUND);
+        assert!(imgs.is_empty());
+    }
+    #[test]
+    fn original_url() {
+        assert_eq!(
+            LastFm::original_url("https://lastfm.freetls.fastly.net/i/u/300x300/a.png"),
+            Some("https://lastfm.freetls.fastly.net/i/u/a.png".to_string())
+        );
+        assert_eq!(LastFm::original_url("https://example.com/a.png"), None);
+    }
+}
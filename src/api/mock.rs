@@ -0,0 +1,158 @@
+//! Local http server and helpers for source tests
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use iced::futures::{
+    StreamExt,
+    channel::mpsc::{self, Receiver, Sender},
+};
+use tokio::runtime::Runtime;
+
+use crate::{
+    api::{
+        queue::{QueueMessage, TagsInput},
+        shared::{self, WebSource},
+    },
+    app::{iced_app::Message, img::SongImg},
+};
+
+/// Response for every request whose path and query contain `pattern`
+pub struct Route {
+    pub pattern: &'static str,
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+impl Route {
+    pub fn new(pattern: &'static str, content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            pattern,
+            status: "200 OK",
+            content_type,
+            headers: Vec::new(),
+            body,
+        }
+    }
+    pub fn json(pattern: &'static str, body: &str) -> Self {
+        Self::new(pattern, "application/json", body.as_bytes().to_vec())
+    }
+    pub fn jpeg(pattern: &'static str) -> Self {
+        let body = include_bytes!("../../resources/preview.jpg").to_vec();
+        Self::new(pattern, "image/jpeg", body)
+    }
+}
+
+pub struct MockServer {
+    listener: TcpListener,
+    /// `http://127.0.0.1:port` without trailing slash
+    pub url: String,
+}
+impl MockServer {
+    pub fn bind() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        Self { listener, url }
+    }
+    /// Fixtures use `http://mock` for urls that point back to the server
+    pub fn fixture(&self, json: &str) -> String {
+        json.replace("http://mock", &self.url)
+    }
+    /// Serve on a background thread until the test exits, returns requested paths
+    pub fn serve(self, routes: Vec<Route>) -> Arc<Mutex<Vec<String>>> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&mut stream);
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok() && line.trim() != "" {
+                    line.clear();
+                }
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or("")
+                    .to_string();
+                log.lock().unwrap().push(path.clone());
+
+                let res = match routes.iter().find(|r| path.contains(r.pattern)) {
+                    Some(route) => {
+                        let mut head = format!(
+                            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                            route.status,
+                            route.content_type,
+                            route.body.len()
+                        );
+                        for (key, value) in &route.headers {
+                            head.push_str(&format!("{key}: {value}\r\n"));
+                        }
+                        head.push_str("\r\n");
+                        [head.into_bytes(), route.body.clone()].concat()
+                    }
+                    None => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&res);
+            }
+        });
+        requests
+    }
+}
+
+/// Song searched for, `folder_images` is empty so only web results come back
+pub fn tags(artist: &str, title: &str, album: &str) -> TagsInput {
+    TagsInput {
+        id: 0,
+        hash: 0,
+        artist: Some(artist.to_string()),
+        title: Some(title.to_string()),
+        album: Some(album.to_string()),
+        folder_images: Vec::new(),
+    }
+}
+/// Serve `routes` and run the source `build` makes from the channel and the server url
+/// * returns the images sent, requested paths and the server url
+pub fn source_test<T: WebSource>(
+    routes: impl FnOnce(&MockServer) -> Vec<Route>,
+    build: impl FnOnce(Sender<Message>, &str) -> T,
+) -> (Vec<SongImg>, Vec<String>, String) {
+    let server = MockServer::bind();
+    let url = server.url.clone();
+    let routes = routes(&server);
+    let requests = server.serve(routes);
+    let (tx, rx) = mpsc::channel(20);
+    let imgs = run_source(build(tx, &url), rx);
+    let requests = requests.lock().unwrap().clone();
+    (imgs, requests, url)
+}
+/// Run both prompts of a source and collect every image it sent, with probed resolutions
+fn run_source<T: WebSource>(src: T, rx: Receiver<Message>) -> Vec<SongImg> {
+    let rt = Runtime::new().unwrap();
+    let _ = rt.block_on(shared::init_source(src));
+    let messages: Vec<Message> = rt.block_on(rx.collect());
+    let mut imgs = Vec::new();
+    let mut probed = Vec::new();
+    for mes in messages {
+        match mes {
+            Message::FromQueue(_, _, QueueMessage::GotArt(img)) => imgs.push(img),
+            Message::FromQueue(_, _, QueueMessage::Resolution(url, res)) => probed.push((url, res)),
+            _ => (),
+        }
+    }
+    for img in &mut imgs {
+        img.take_probed(&mut probed);
+    }
+    imgs
+}
@@ -1,7 +1,17 @@
 mod bandcamp;
+pub mod cache;
+mod deezer;
+mod discogs;
+mod itunes;
+mod lastfm;
+#[cfg(test)]
+mod mock;
 mod musicbrainz;
+pub mod net;
+mod probe;
 mod qobuz;
 pub mod queue;
+pub mod registry;
 pub mod shared;
 mod yt;
 mod yt_music;
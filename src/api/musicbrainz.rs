@@ -1,19 +1,32 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use anyhow::Error;
 use iced::futures::channel::mpsc::Sender;
-use log::info;
+use log::{info, warn};
 use musicbrainz_rs::{
     FetchCoverart, MusicBrainzClient, Search,
     entity::{
         CoverartResponse,
+        artist_credit::ArtistCredit,
+        recording::{Recording, RecordingSearchQuery},
         release::{Release, ReleaseSearchQuery},
+        release_group::ReleaseGroup,
     },
 };
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
 
 use crate::api::{
+    net,
     queue::{
         Source::{self, *},
         TagsInput,
     },
+    registry::ApiKeys,
     shared::{self, WebSource, send_song},
 };
 use crate::app::{
@@ -24,6 +37,7 @@ use crate::app::{
         SongImg,
     },
 };
+use crate::parser::release_tags::ReleaseInfo;
 
 pub struct Musicbrainz {
     tags: TagsInput,
@@ -32,23 +46,57 @@ pub struct Musicbrainz {
 }
 
 const SEARCH_LIMIT: usize = 20;
+/// musicbrainz.org allows one request per second from an ip
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Searches that failed, usually with 503 on a busy server, are tried once more
+const RETRIES: usize = 1;
+/// Next free request slot, shared by every song
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Release found by a search with what its art lookup needs, kept in the cache
+/// * `group`: id and title of the release group
+#[derive(Serialize, Deserialize)]
+struct Hit {
+    feedback: String,
+    info: ReleaseInfo,
+    group: Option<(String, String)>,
+}
+impl Hit {
+    fn new(release: &Release, recording: Option<&Recording>) -> Self {
+        Self {
+            feedback: Musicbrainz::feedback(release),
+            info: Musicbrainz::release_info(release, recording),
+            group: release
+                .release_group
+                .as_ref()
+                .map(|g| (g.id.clone(), g.title.clone())),
+        }
+    }
+}
+/// Cover art archive image, kept in the cache
+#[derive(Serialize, Deserialize)]
+struct ArtImage {
+    image: String,
+    thumbnail: Option<String>,
+}
+
 impl WebSource for Musicbrainz {
     fn build_title_pompt(&self, title: &str, artist: &str) -> String {
-        ReleaseSearchQuery::query_builder()
-            .artist(title)
+        RecordingSearchQuery::query_builder()
+            .recording(title)
             .and()
-            .release(artist)
+            .artist(artist)
             .build()
     }
     fn build_album_pompt(&self, album: &str, artist: &str) -> String {
         ReleaseSearchQuery::query_builder()
-            .artist(album)
+            .release(album)
             .and()
-            .release(artist)
+            .artist(artist)
             .build()
     }
     const ALBUM_SOURCE: Source = BrainzAlbum;
-    const TITLE_SOURCE: Source = BandcampTitle;
+    const TITLE_SOURCE: Source = BrainzTitle;
 
     fn tags_ref(&self) -> &TagsInput {
         &self.tags
@@ -60,79 +108,252 @@ impl WebSource for Musicbrainz {
     fn tx_clone(&self) -> Sender<Message> {
         self.tx.clone()
     }
-    async fn init(tags: TagsInput, tx: Sender<Message>) -> Result<(), Error> {
-        let this = Self {
-            tags,
-            tx,
-            b_client: MusicBrainzClient::default(),
+    fn client_ref(&self) -> &Client {
+        &self.b_client.reqwest_client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
+        // musicbrainz_rs sends its searches and cover art lookups outside of `net` limits,
+        // searches are spaced by `wait_for_slot`. Images go through `net`
+        let b_client = MusicBrainzClient {
+            reqwest_client: net::client(),
+            ..Default::default()
         };
+        let this = Self { tags, tx, b_client };
         shared::init_source(this).await?;
         Ok(())
     }
     async fn with_prompt(&self, query: &str, src: Source) -> Result<(), Error> {
-        // TODO: rate limit throws internal crate error
-        let query_result = Release::search(query.to_string())
-            .execute_with_client(&self.b_client)
-            .await?;
-
-        info!(
-            "Found {} matches in song {}",
-            query_result.count, self.tags.id
-        );
+        let hits = if src == BrainzAlbum {
+            let key = format!("musicbrainz release search: {query}");
+            shared::cached_results(&key, self.search_releases(query)).await?
+        } else {
+            let key = format!("musicbrainz recording search: {query}");
+            shared::cached_results(&key, self.search_recordings(query)).await?
+        };
+        info!("Found {} releases in song {}", hits.len(), self.tags.id);
 
-        for release in query_result.entities {
-            let artists = if let Some(vec) = release.artist_credit {
-                let mut line = "".to_string();
-                for artist in vec {
-                    line.push_str(&artist.name);
-                }
-                line
-            } else {
-                "unknown".to_string()
+        // releases of one group often have no art of their own and share the group's
+        let mut groups_done: Vec<String> = Vec::new();
+        for hit in hits.into_iter().take(SEARCH_LIMIT) {
+            let id = &hit.info.release_id;
+            if self
+                .send_coverart::<Release>(id, &hit.feedback, &hit.info, src)
+                .await
+            {
+                continue;
+            }
+            let Some((group_id, group_title)) = hit.group else {
+                continue;
             };
-            let feedback = format!("artist: {}, release: {},", artists, release.title);
-
-            let releases_result = Release::fetch_coverart()
-                .id(&release.id)
-                .execute_with_client(&self.b_client)
+            if groups_done.contains(&group_id) {
+                continue;
+            }
+            groups_done.push(group_id.clone());
+            let feedback = format!("{}\nart of release group: {group_title}", hit.feedback);
+            self.send_coverart::<ReleaseGroup>(&group_id, &feedback, &hit.info, src)
                 .await;
+        }
 
-            let client = &self.b_client.reqwest_client;
-            if let Ok(cover_response) = releases_result
-                && let CoverartResponse::Json(cover) = cover_response
+        Ok(())
+    }
+}
+impl Musicbrainz {
+    async fn search_releases(&self, query: &str) -> Result<Vec<Hit>, Error> {
+        let mut tries = 0;
+        loop {
+            wait_for_slot().await;
+            match Release::search(query.to_string())
+                .execute_with_client(&self.b_client)
+                .await
             {
-                for img in cover.images {
-                    let new_song = if let Some(thumb) = img.thumbnails.res_250 {
-                        let res = shared::get_img(client, vec![thumb]).await;
-                        if res.is_err() {
-                            continue;
-                        }
-                        RawPreview(vec![img.image.clone()], res.unwrap())
-                    } else if let Some(thumb) = img.thumbnails.small {
-                        let res = shared::get_img(client, vec![thumb]).await;
-                        if res.is_err() {
-                            continue;
-                        }
-                        RawPreview(vec![img.image.clone()], res.unwrap())
-                    } else {
-                        let res = shared::get_img(client, vec![img.image.clone()]).await;
-                        if res.is_err() {
-                            continue;
-                        }
-                        info!("only full picture available for {:?}", self.tags.hash);
-                        Raw(res.unwrap())
-                    };
-                    let new_img = SongImg::new(
-                        ImgFormat::from_url(&img.image),
-                        new_song,
-                        src,
-                        feedback.clone(),
-                    );
-                    send_song(self, new_img).await;
+                Ok(res) => return Ok(res.entities.iter().map(|r| Hit::new(r, None)).collect()),
+                Err(e) if tries < RETRIES => {
+                    warn!("musicbrainz release search failed, retrying: {e}");
+                    tries += 1;
                 }
+                Err(e) => return Err(e.into()),
             }
         }
+    }
+    /// Releases of every found recording, without repeats
+    async fn search_recordings(&self, query: &str) -> Result<Vec<Hit>, Error> {
+        let mut tries = 0;
+        let recordings = loop {
+            wait_for_slot().await;
+            match Recording::search(query.to_string())
+                .execute_with_client(&self.b_client)
+                .await
+            {
+                Ok(res) => break res.entities,
+                Err(e) if tries < RETRIES => {
+                    warn!("musicbrainz recording search failed, retrying: {e}");
+                    tries += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let mut hits: Vec<Hit> = Vec::new();
+        for mut recording in recordings {
+            for release in recording.releases.take().unwrap_or_default() {
+                if !hits.iter().any(|hit| hit.info.release_id == release.id) {
+                    hits.push(Hit::new(&release, Some(&recording)));
+                }
+            }
+        }
+        Ok(hits)
+    }
+    fn feedback(release: &Release) -> String {
+        let artists = match &release.artist_credit {
+            Some(credits) => Self::credit_line(credits),
+            None => "unknown".to_string(),
+        };
+        let mut feedback = format!("artist: {}, release: {},", artists, release.title);
+        if let Some(date) = &release.date {
+            feedback.push_str(&format!("\ndate: {}", date.0));
+        }
+        feedback.push_str(&format!(
+            "\nurl: https://musicbrainz.org/release/{}",
+            release.id
+        ));
+        feedback
+    }
+    /// Recording search returns only the medium and track of the recording in `media`
+    fn release_info(release: &Release, recording: Option<&Recording>) -> ReleaseInfo {
+        let artist_ids = |credits: &Option<Vec<ArtistCredit>>| {
+            credits
+                .iter()
+                .flatten()
+                .map(|c| c.artist.id.clone())
+                .collect()
+        };
+        let mut info = ReleaseInfo {
+            release_id: release.id.clone(),
+            release_group_id: release.release_group.as_ref().map(|g| g.id.clone()),
+            album_artist_ids: artist_ids(&release.artist_credit),
+            album_artist: release.artist_credit.as_deref().map(Self::credit_line),
+            year: release
+                .date
+                .as_ref()
+                .and_then(|d| d.0.get(..4)?.parse().ok()),
+            ..Default::default()
+        };
+        let Some(recording) = recording else {
+            return info;
+        };
+        info.recording_id = Some(recording.id.clone());
+        info.artist_ids = artist_ids(&recording.artist_credit);
 
-        Ok(())
+        let media = release.media.as_deref().unwrap_or_default();
+        let total_discs = u16::try_from(media.len()).ok();
+        for (i, medium) in media.iter().enumerate() {
+            let Some(track) = medium.tracks.as_ref().and_then(|t| t.first()) else {
+                continue;
+            };
+            let disc = medium.position.unwrap_or(i as u32 + 1);
+            info.track = u16::try_from(track.position)
+                .ok()
+                .zip(u16::try_from(medium.track_count).ok());
+            info.disc = u16::try_from(disc).ok().zip(total_discs);
+        }
+        info
+    }
+    fn credit_line(credits: &[ArtistCredit]) -> String {
+        let mut line = "".to_string();
+        for artist in credits {
+            line.push_str(&artist.name);
+            line.push_str(artist.joinphrase.as_deref().unwrap_or(""));
+        }
+        line
+    }
+    /// Cover art archive entry of a release or release group, false if it has no images
+    async fn send_coverart<T: FetchCoverart>(
+        &self,
+        id: &str,
+        feedback: &str,
+        info: &ReleaseInfo,
+        src: Source,
+    ) -> bool {
+        let key = format!("cover art archive: {id}");
+        let Ok(images) = shared::cached_results(&key, self.coverart::<T>(id)).await else {
+            return false;
+        };
+        if images.is_empty() {
+            return false;
+        }
+
+        let client = &self.b_client.reqwest_client;
+        for img in images {
+            let new_song = if let Some(thumb) = img.thumbnail {
+                let Ok(res) = shared::get_img(client, vec![thumb]).await else {
+                    continue;
+                };
+                RawPreview(vec![img.image.clone()], res)
+            } else {
+                let Ok(res) = shared::get_img(client, vec![img.image.clone()]).await else {
+                    continue;
+                };
+                info!("only full picture available for {:?}", self.tags.hash);
+                Raw(res)
+            };
+            let new_img = SongImg::new(
+                ImgFormat::from_url(&img.image),
+                new_song,
+                src,
+                feedback.to_string(),
+            )
+            .with_release(info.clone());
+            send_song(self, new_img).await;
+        }
+        true
+    }
+    async fn coverart<T: FetchCoverart>(&self, id: &str) -> Result<Vec<ArtImage>, Error> {
+        let cover_response = T::fetch_coverart()
+            .id(id)
+            .execute_with_client(&self.b_client)
+            .await?;
+        let CoverartResponse::Json(cover) = cover_response else {
+            return Ok(Vec::new());
+        };
+        Ok(cover
+            .images
+            .into_iter()
+            .map(|img| ArtImage {
+                thumbnail: img.thumbnails.res_250.or(img.thumbnails.small),
+                image: img.image,
+            })
+            .collect())
+    }
+}
+
+/// Sleep until the next free slot of the musicbrainz.org rate limit
+async fn wait_for_slot() {
+    let wait = reserve_slot(&mut NEXT_REQUEST.lock().unwrap(), Instant::now());
+    if !wait.is_zero() {
+        sleep(wait).await;
+    }
+}
+/// Take the first slot after `now` and return how long to wait for it
+fn reserve_slot(next: &mut Option<Instant>, now: Instant) -> Duration {
+    let slot = next.map_or(now, |n| n.max(now));
+    *next = Some(slot + REQUEST_INTERVAL);
+    slot - now
+}
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::api::musicbrainz::{REQUEST_INTERVAL, reserve_slot};
+
+    #[test]
+    fn slots_are_spaced() {
+        let start = Instant::now();
+        let mut next = None;
+        assert_eq!(reserve_slot(&mut next, start), Duration::ZERO);
+        assert_eq!(reserve_slot(&mut next, start), REQUEST_INTERVAL);
+        assert_eq!(reserve_slot(&mut next, start), REQUEST_INTERVAL * 2);
+        // slots left unused in the past are not saved up
+        let later = start + REQUEST_INTERVAL * 10;
+        assert_eq!(reserve_slot(&mut next, later), Duration::ZERO);
+        assert_eq!(reserve_slot(&mut next, later), REQUEST_INTERVAL);
     }
 }
@@ -0,0 +1,296 @@
+//! Shared http client, every request waits for a global slot and for its host's rate limit.
+//! Youtube searches and musicbrainz api calls are sent by their crates and are not limited here,
+//! `musicbrainz` spaces its own searches and youtube has no known limit
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::{
+    Client, RequestBuilder, Response, StatusCode, Url,
+    header::{HeaderMap, RETRY_AFTER},
+};
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::sleep,
+};
+
+/// Requests in flight across all songs and sources
+const MAX_IN_FLIGHT: usize = 8;
+/// Requests per second and burst of hosts not listed in `HOST_RATES`
+const DEFAULT_RATE: (f64, f64) = (5.0, 5.0);
+/// Hosts that throttle early, matched by the end of the host name
+const HOST_RATES: [(&str, (f64, f64)); 3] = [
+    ("bandcamp.com", (1.0, 2.0)),
+    ("qobuz.com", (1.0, 2.0)),
+    ("discogs.com", (1.0, 2.0)),
+];
+/// Retries of a request answered with 429 or 503
+const MAX_RETRIES: u32 = 3;
+/// Wait before the first retry when there is no `Retry-After`, doubled every retry
+const BACKOFF: Duration = Duration::from_secs(1);
+/// Longer `Retry-After` is cut to this
+const MAX_WAIT: Duration = Duration::from_secs(60);
+/// Query parameters that are never logged or written to the cache
+const SECRET_PARAMS: [&str; 2] = ["api_key", "token"];
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    // discogs rejects requests without a user agent
+    Client::builder()
+        .user_agent(concat!("mass_coverart/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+static IN_FLIGHT: Semaphore = Semaphore::const_new(MAX_IN_FLIGHT);
+static BUCKETS: LazyLock<Mutex<HashMap<String, Bucket>>> = LazyLock::new(Default::default);
+
+/// Token bucket of one host
+/// * `rate`: tokens added per second
+/// * `burst`: most tokens kept
+/// * `blocked_until`: set from `Retry-After`, nothing is sent to the host before it
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+impl Bucket {
+    fn new(host: &str, now: Instant) -> Self {
+        let (rate, burst) = HOST_RATES
+            .iter()
+            .find(|(name, _)| host.split(':').next().unwrap_or(host).ends_with(name))
+            .map_or(DEFAULT_RATE, |(_, rate)| *rate);
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: now,
+            blocked_until: None,
+        }
+    }
+    /// Take a token, or how long to wait for one
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.blocked_until = None;
+        }
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = self.last.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// Url without `SECRET_PARAMS`, for logs and cache entries
+pub fn redact(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let kept: Vec<_> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or_default();
+            !SECRET_PARAMS.contains(&name)
+        })
+        .collect();
+    if kept.is_empty() {
+        base.to_string()
+    } else {
+        format!("{base}?{}", kept.join("&"))
+    }
+}
+/// Errors print their url, keys are taken out of it
+fn redact_error(mut e: reqwest::Error) -> reqwest::Error {
+    if let Some(url) = e.url_mut()
+        && let Ok(clean) = Url::parse(&redact(url.as_str()))
+    {
+        *url = clean;
+    }
+    e
+}
+/// Client shared by every source, clones share one connection pool
+pub fn client() -> Client {
+    CLIENT.clone()
+}
+async fn wait_turn(host: &str) {
+    loop {
+        let wait = {
+            let mut buckets = BUCKETS.lock().unwrap();
+            let now = Instant::now();
+            buckets
+                .entry(host.to_string())
+                .or_insert_with(|| Bucket::new(host, now))
+                .take(now)
+        };
+        match wait {
+            Some(wait) => sleep(wait).await,
+            None => return,
+        }
+    }
+}
+fn block(host: &str, until: Instant) {
+    let mut buckets = BUCKETS.lock().unwrap();
+    let bucket = buckets
+        .entry(host.to_string())
+        .or_insert_with(|| Bucket::new(host, Instant::now()));
+    bucket.blocked_until = bucket.blocked_until.max(Some(until));
+}
+/// `Retry-After` in seconds or as a date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let wait = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+        }
+    };
+    Some(wait.min(MAX_WAIT))
+}
+/// Response with the global slot it holds, 429 and 503 are retried after
+/// `Retry-After` or a doubling backoff, and hold back the whole host meanwhile
+async fn execute(req: RequestBuilder) -> reqwest::Result<(Response, SemaphorePermit<'static>)> {
+    let (client, req) = req.build_split();
+    let mut req = req?;
+    let url = req.url();
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut retries = 0;
+    loop {
+        // bodies that are streamed can not be sent twice
+        let next = req.try_clone().filter(|_| retries < MAX_RETRIES);
+        wait_turn(&host).await;
+        let permit = IN_FLIGHT
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        let response = client.execute(req).await.map_err(redact_error)?;
+        let status = response.status();
+        let throttled =
+            status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+        let Some(next) = next.filter(|_| throttled) else {
+            return Ok((response, permit));
+        };
+        let wait = retry_after(response.headers()).unwrap_or(BACKOFF * 2u32.pow(retries));
+        warn!(
+            "{host} answered {status}, retrying in {}ms",
+            wait.as_millis()
+        );
+        block(&host, Instant::now() + wait);
+        drop(permit);
+        req = next;
+        retries += 1;
+    }
+}
+/// Send through the global limit and the host's rate limit, see `execute`.
+/// Keep the permit until the body is read, or dropped
+pub async fn send(req: RequestBuilder) -> reqwest::Result<(Response, SemaphorePermit<'static>)> {
+    execute(req).await
+}
+/// Status and whole body, the global slot is held until the body is read
+pub async fn fetch(req: RequestBuilder) -> reqwest::Result<(StatusCode, Bytes)> {
+    let (response, _permit) = execute(req).await?;
+    let status = response.status();
+    info!("Getting body, url: {}", redact(response.url().as_str()));
+    Ok((status, response.bytes().await.map_err(redact_error)?))
+}
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use reqwest::{
+        StatusCode,
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+    };
+    use tokio::runtime::Runtime;
+
+    use crate::api::{
+        mock::{MockServer, Route},
+        net::{Bucket, MAX_RETRIES, MAX_WAIT, client, fetch, redact, retry_after},
+    };
+
+    #[test]
+    fn host_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new("daftpunk.bandcamp.com", now);
+        assert_eq!(bucket.take(now), None);
+        assert_eq!(bucket.take(now), None);
+        assert_eq!(bucket.take(now), Some(Duration::from_secs(1)));
+        let later = now + Duration::from_millis(500);
+        assert_eq!(bucket.take(later), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take(now + Duration::from_secs(1)), None);
+
+        bucket.blocked_until = Some(now + Duration::from_secs(5));
+        let blocked = now + Duration::from_secs(2);
+        assert_eq!(bucket.take(blocked), Some(Duration::from_secs(3)));
+        // burst is refilled by the time the block ends
+        let free = now + Duration::from_secs(5);
+        assert_eq!(bucket.take(free), None);
+        assert_eq!(bucket.take(free), None);
+        assert!(bucket.take(free).is_some());
+    }
+    #[test]
+    fn secrets_redacted() {
+        assert_eq!(
+            redact(
+                "http://ws.audioscrobbler.com/2.0/?method=album.getinfo&api_key=abc&format=json"
+            ),
+            "http://ws.audioscrobbler.com/2.0/?method=album.getinfo&format=json"
+        );
+        assert_eq!(redact("http://host/?api_key=abc"), "http://host/");
+        assert_eq!(redact("http://host/a.jpg"), "http://host/a.jpg");
+    }
+    #[test]
+    fn retry_after_parsed() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("86400"));
+        assert_eq!(retry_after(&headers), Some(MAX_WAIT));
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+    #[test]
+    fn throttled_retried() {
+        let server = MockServer::bind();
+        let url = server.url.clone();
+        let mut route = Route::json("/busy", "{}");
+        route.status = "429 Too Many Requests";
+        route.headers.push(("Retry-After", "0".to_string()));
+        let requests = server.serve(vec![route, Route::json("/ok", "{}")]);
+
+        let rt = Runtime::new().unwrap();
+        let (status, _) = rt
+            .block_on(fetch(client().get(format!("{url}/busy"))))
+            .unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let (status, body) = rt
+            .block_on(fetch(client().get(format!("{url}/ok"))))
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(&body[..], b"{}");
+        let requests = requests.lock().unwrap();
+        let busy = requests.iter().filter(|r| r.contains("/busy")).count();
+        assert_eq!(busy, MAX_RETRIES as usize + 1);
+    }
+}
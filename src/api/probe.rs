@@ -0,0 +1,138 @@
+//! Full resolution of a preview without downloading the full image
+use std::io::Cursor;
+
+use image::ImageReader;
+use log::info;
+use reqwest::{Client, header::RANGE};
+
+use crate::api::{cache, net};
+
+/// Jpeg markers before the frame header are usually far smaller, exif thumbnails can push it back
+const HEAD_BYTES: usize = 64 * 1024;
+
+/// First of `urls` that answers, read up to the image header.
+/// Falls back to the size the url asks the server for
+pub async fn resolution(client: &Client, urls: &[String]) -> Option<(u32, u32)> {
+    for url in urls {
+        if let Some(res) = from_head(client, url).await {
+            info!("probed {}x{} from {url}", res.0, res.1);
+            return Some(res);
+        }
+    }
+    urls.first().and_then(|url| from_url(url))
+}
+async fn from_head(client: &Client, url: &str) -> Option<(u32, u32)> {
+    if let Some(pic) = cache::get(url, cache::IMG_TTL).await {
+        return dimensions(&pic);
+    }
+    let (mut response, _permit) = net::send(
+        client
+            .get(url)
+            .header(RANGE, format!("bytes=0-{}", HEAD_BYTES - 1)),
+    )
+    .await
+    .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    // servers that ignore the range send everything, stop reading early
+    let mut head = Vec::new();
+    while head.len() < HEAD_BYTES
+        && let Some(chunk) = response.chunk().await.ok()?
+    {
+        head.extend_from_slice(&chunk);
+    }
+    dimensions(&head)
+}
+/// Dimensions from the header, `head` may be cut anywhere after it
+fn dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(head))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+/// Smallest side taken from a url, smaller numbers are ids or indices
+const MIN_URL_SIDE: u32 = 50;
+/// Hosts whose `..._600.jpg` suffix is the size, elsewhere it can be an index
+const SUFFIX_SIZE_HOSTS: [&str; 1] = ["qobuz.com"];
+
+/// Size encoded in the url by the cdn, `_max` style urls have none
+/// * `.../600x600bb.jpg`, `.../1800x1800-000000-80-0-0.jpg`
+/// * `..._600.jpg` on [SUFFIX_SIZE_HOSTS]
+/// * youtube thumbnail names
+fn from_url(url: &str) -> Option<(u32, u32)> {
+    from_url_name(url).filter(|&(w, h)| w.min(h) >= MIN_URL_SIDE)
+}
+fn from_url_name(url: &str) -> Option<(u32, u32)> {
+    let path = url.split(['?', '#']).next()?;
+    let file = path.rsplit('/').next()?;
+    let host = path.split("://").nth(1)?.split('/').next()?;
+    let stem = file.split('.').next()?;
+    match stem {
+        "maxresdefault" => return Some((1280, 720)),
+        "sddefault" => return Some((640, 480)),
+        "hqdefault" => return Some((480, 360)),
+        "mqdefault" => return Some((320, 180)),
+        _ => (),
+    }
+    let number = |s: &str| {
+        let digits: String = s.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse::<u32>().ok()
+    };
+    if let Some((w, rest)) = stem.split_once('x')
+        && let (Ok(w), Some(h)) = (w.parse::<u32>(), number(rest))
+    {
+        return Some((w, h));
+    }
+    if !SUFFIX_SIZE_HOSTS
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{h}")))
+    {
+        return None;
+    }
+    let size = number(stem.rsplit_once('_')?.1)?;
+    Some((size, size))
+}
+#[cfg(test)]
+mod tests {
+    use crate::api::probe::{dimensions, from_url};
+
+    #[test]
+    fn url_conventions() {
+        let itunes = "https://is1.mzstatic.com/image/thumb/a.jpg/3000x3000bb.jpg";
+        assert_eq!(from_url(itunes), Some((3000, 3000)));
+        let deezer = "https://cdn-images.dzcdn.net/images/cover/h/1800x1800-000000-80-0-0.jpg";
+        assert_eq!(from_url(deezer), Some((1800, 1800)));
+        let qobuz = "https://static.qobuz.com/images/covers/ab/cd/abcd_600.jpg";
+        assert_eq!(from_url(qobuz), Some((600, 600)));
+        let youtube = "https://img.youtube.com/vi/id/maxresdefault.jpg";
+        assert_eq!(from_url(youtube), Some((1280, 720)));
+        assert_eq!(
+            from_url("https://static.qobuz.com/images/covers/ab/cd/abcd_max.jpg"),
+            None
+        );
+        assert_eq!(from_url("https://i.discogs.com/primary.jpeg"), None);
+    }
+    #[test]
+    fn url_numbers_not_sizes() {
+        let bandcamp = "https://f4.bcbits.com/img/a1234567890_0.jpg";
+        assert_eq!(from_url(bandcamp), None);
+        assert_eq!(
+            from_url("https://f4.bcbits.com/img/a1234567890_7.jpg"),
+            None
+        );
+        let tiny = "https://static.qobuz.com/images/covers/ab/cd/abcd_0.jpg";
+        assert_eq!(from_url(tiny), None);
+        assert_eq!(from_url("https://example.com/0x0.jpg"), None);
+        assert_eq!(from_url("https://example.com/cover_1200.jpg"), None);
+    }
+    #[test]
+    fn header_of_cut_file() {
+        let full = include_bytes!("../../resources/preview.jpg");
+        let res = dimensions(full);
+        assert!(res.is_some());
+        assert_eq!(dimensions(&full[..full.len() / 2]), res);
+        assert_eq!(dimensions(&full[..4]), None);
+    }
+}
@@ -6,10 +6,12 @@ use reqwest::Client;
 
 use crate::{
     api::{
+        net,
         queue::{
             Source::{self, *},
             TagsInput,
         },
+        registry::ApiKeys,
         shared::{self, WebSource, send_song},
     },
     app::{
@@ -45,11 +47,14 @@ impl WebSource for Qobuz {
     fn tx_clone(&self) -> Sender<Message> {
         self.tx.clone()
     }
-    async fn init(tags: TagsInput, tx: Sender<Message>) -> Result<(), Error> {
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
         let this = Self {
             tags,
             tx,
-            client: Client::new(),
+            client: net::client(),
         };
         shared::init_source(this).await?;
         Ok(())
@@ -65,7 +70,7 @@ impl WebSource for Qobuz {
 
         info!("Fetching search: {}", search_url);
 
-        let search_results_html = self.client.get(&search_url).send().await?.text().await?;
+        let search_results_html = shared::get_page(self.client.get(&search_url)).await?;
 
         let re = RegexBuilder::new(r#"<div class="ReleaseCard">\s*<img\s*class="CoverModel"\s*src="(?<thumb>(?<imgBase>[^_]+)[^\"]+)[^>]+>.+?<a\s*class="ReleaseCardInfosTitle"\s*href="(?<url>[^\"]+)"[^>]+data-title="(?<title>[^\"]+)""#)
         .multi_line(true)
@@ -1,27 +1,22 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
+use bytes::Bytes;
 use iced::{Task, futures::channel::mpsc::Sender, stream::channel, task::Handle, widget::image};
 use log::{info, warn};
-use tokio::task::JoinSet;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, task::JoinSet};
 
 use crate::{
-    api::{
-        bandcamp::Bandcamp,
-        musicbrainz::Musicbrainz,
-        qobuz::Qobuz,
-        shared::{WebSource, send_message, send_message_from_source},
-        yt::{self, Youtube},
-        yt_music::YoutubeMus,
-    },
+    api::{registry::SourceRegistry, shared::send_message},
     app::{
         iced_app::Message,
-        img::SongImg,
+        img::{ImageProgress, ImgFormat, SongImg},
         song::{SongHash, SongId},
     },
     parser::file_parser::TagData,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Source {
     LocalFile,
     YoutubeAlbum,
@@ -34,6 +29,14 @@ pub enum Source {
     QobuzAlbum,
     YoutubeMusAlbum,
     YoutubeMusTitle,
+    LastFmAlbum,
+    LastFmTitle,
+    DeezerAlbum,
+    DeezerTitle,
+    ItunesAlbum,
+    ItunesTitle,
+    DiscogsAlbum,
+    DiscogsTitle,
 }
 impl Source {
     pub fn get_weight(&self) -> i32 {
@@ -46,6 +49,13 @@ impl Source {
             Self::BandcampTitle => 15,
             Self::QobuzTitle => 15,
             Self::QobuzAlbum => 15,
+            Self::LastFmAlbum => 15,
+            // album of the matched track, a single or compilation as often as not
+            Self::LastFmTitle => 12,
+            Self::DeezerAlbum => 15,
+            Self::ItunesAlbum => 15,
+            Self::DiscogsAlbum => 20,
+            Self::DiscogsTitle => 15,
             _ => 10,
         }
     }
@@ -64,11 +74,20 @@ impl Display for Source {
             Self::QobuzAlbum => write!(f, "qobuz.com (%artist% %album%)"),
             Self::YoutubeMusAlbum => write!(f, "music.youtube.com (%artist% %album%)"),
             Self::YoutubeMusTitle => write!(f, "music.youtube.com (%artist% %title%)"),
+            Self::LastFmAlbum => write!(f, "last.fm (%artist% %album%)"),
+            Self::LastFmTitle => write!(f, "last.fm (%artist% %title%)"),
+            Self::DeezerAlbum => write!(f, "deezer.com (%artist% %album%)"),
+            Self::DeezerTitle => write!(f, "deezer.com (%artist% %title%)"),
+            Self::ItunesAlbum => write!(f, "itunes.apple.com (%artist% %album%)"),
+            Self::ItunesTitle => write!(f, "itunes.apple.com (%artist% %title%)"),
+            Self::DiscogsAlbum => write!(f, "discogs.com (%artist% %album%)"),
+            Self::DiscogsTitle => write!(f, "discogs.com (%artist% %title%)"),
         }
     }
 }
 
 /// Song information to find album cover in queue
+/// * `folder_images`: cover files next to the track, sent before web sources
 #[derive(Clone)]
 pub struct TagsInput {
     pub id: SongId,
@@ -76,6 +95,7 @@ pub struct TagsInput {
     pub artist: Option<String>,
     pub title: Option<String>,
     pub album: Option<String>,
+    pub folder_images: Vec<PathBuf>,
 }
 impl TagsInput {
     pub fn from_data(id: SongId, hash: u64, data: &TagData) -> Self {
@@ -85,36 +105,35 @@ impl TagsInput {
             artist: data.artist.clone(),
             title: data.title.clone(),
             album: data.album.clone(),
+            folder_images: data.folder_images.clone(),
         }
     }
 }
 #[derive(Clone, Debug)]
 pub enum QueueMessage {
     GotArt(SongImg),
+    /// Full size of the preview with this first url, probed after the preview was sent
+    Resolution(String, (u32, u32)),
     SetSources(i32, i32),
     SourceFinished,
 }
 
 pub struct Queue;
 impl Queue {
-    pub fn init(tags: TagsInput) -> (Task<Message>, Handle) {
-        Task::stream(channel(20, move |tx| Self::queue(tags, tx))).abortable()
+    /// * `sources`: copy of the registry at the moment of confirm
+    pub fn init(tags: TagsInput, sources: SourceRegistry) -> (Task<Message>, Handle) {
+        Task::stream(channel(20, move |tx| Self::queue(tags, sources, tx))).abortable()
     }
-    pub const TOTAL_SOURCES: i32 = 5;
-    async fn queue(tags: TagsInput, tx: Sender<Message>) {
+    pub async fn queue(tags: TagsInput, sources: SourceRegistry, tx: Sender<Message>) {
+        let enabled = sources.enabled();
+        let total = enabled.len() as i32;
         let mut set = JoinSet::new();
-        set.spawn(Musicbrainz::init(tags.clone(), tx.clone()));
-        set.spawn(YoutubeMus::init(tags.clone(), tx.clone()));
-        set.spawn(Youtube::init(tags.clone(), tx.clone()));
-        set.spawn(Bandcamp::init(tags.clone(), tx.clone()));
-        set.spawn(Qobuz::init(tags.clone(), tx.clone()));
+        for kind in enabled {
+            kind.spawn(&mut set, tags.clone(), tx.clone(), sources.keys.clone());
+        }
         info!("queue is started for {}", tags.id);
-        send_message(
-            &tags,
-            &mut tx.clone(),
-            QueueMessage::SetSources(0, Self::TOTAL_SOURCES),
-        )
-        .await;
+        send_message(&tags, &mut tx.clone(), QueueMessage::SetSources(0, total)).await;
+        Self::send_folder_images(&tags, &tx).await;
 
         while let Some(res) = set.join_next().await {
             let _ = res.inspect_err(|e| warn!("error occurred in queue of {} - {e}", tags.id));
@@ -123,8 +142,27 @@ impl Queue {
         send_message(
             &tags,
             &mut tx.clone(),
-            QueueMessage::SetSources(Self::TOTAL_SOURCES, Self::TOTAL_SOURCES),
+            QueueMessage::SetSources(total, total),
         )
         .await;
     }
+    async fn send_folder_images(tags: &TagsInput, tx: &Sender<Message>) {
+        for path in &tags.folder_images {
+            let bytes = match fs::read(path).await {
+                Ok(bytes) => Bytes::from(bytes),
+                Err(e) => {
+                    warn!("folder image {} was not read: {e}", path.display());
+                    continue;
+                }
+            };
+            let name = path.to_string_lossy().to_string();
+            let img = SongImg::new(
+                ImgFormat::from_url(&name),
+                ImageProgress::Raw(bytes),
+                Source::LocalFile,
+                name,
+            );
+            send_message(tags, &mut tx.clone(), QueueMessage::GotArt(img)).await;
+        }
+    }
 }
@@ -0,0 +1,200 @@
+use anyhow::Error;
+use iced::futures::channel::mpsc::Sender;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::{
+    api::{
+        bandcamp::Bandcamp,
+        deezer::Deezer,
+        discogs::Discogs,
+        itunes::Itunes,
+        lastfm::LastFm,
+        musicbrainz::Musicbrainz,
+        qobuz::Qobuz,
+        queue::{Source, TagsInput},
+        shared::WebSource,
+        yt::Youtube,
+        yt_music::YoutubeMus,
+    },
+    app::iced_app::Message,
+};
+
+/// Every `WebSource` implementation the queue can spawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SourceKind {
+    Musicbrainz,
+    YoutubeMus,
+    Youtube,
+    Bandcamp,
+    Qobuz,
+    LastFm,
+    Deezer,
+    Itunes,
+    Discogs,
+}
+impl SourceKind {
+    pub const ALL: [SourceKind; 9] = [
+        Self::Musicbrainz,
+        Self::YoutubeMus,
+        Self::Youtube,
+        Self::Bandcamp,
+        Self::Qobuz,
+        Self::LastFm,
+        Self::Deezer,
+        Self::Itunes,
+        Self::Discogs,
+    ];
+    pub fn spawn(
+        self,
+        set: &mut JoinSet<Result<(), Error>>,
+        tags: TagsInput,
+        tx: Sender<Message>,
+        keys: ApiKeys,
+    ) {
+        match self {
+            Self::Musicbrainz => set.spawn(Musicbrainz::init(tags, tx, keys)),
+            Self::YoutubeMus => set.spawn(YoutubeMus::init(tags, tx, keys)),
+            Self::Youtube => set.spawn(Youtube::init(tags, tx, keys)),
+            Self::Bandcamp => set.spawn(Bandcamp::init(tags, tx, keys)),
+            Self::Qobuz => set.spawn(Qobuz::init(tags, tx, keys)),
+            Self::LastFm => set.spawn(LastFm::init(tags, tx, keys)),
+            Self::Deezer => set.spawn(Deezer::init(tags, tx, keys)),
+            Self::Itunes => set.spawn(Itunes::init(tags, tx, keys)),
+            Self::Discogs => set.spawn(Discogs::init(tags, tx, keys)),
+        };
+    }
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Musicbrainz => "musicbrainz.com",
+            Self::YoutubeMus => "music.youtube.com",
+            Self::Youtube => "youtube.com",
+            Self::Bandcamp => "bandcamp.com",
+            Self::Qobuz => "qobuz.com",
+            Self::LastFm => "last.fm",
+            Self::Deezer => "deezer.com",
+            Self::Itunes => "itunes.apple.com",
+            Self::Discogs => "discogs.com",
+        }
+    }
+    /// Kind that searches with `src`, none for local files
+    pub fn of(src: Source) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.sources().contains(&src))
+    }
+    /// Album and title search sources
+    pub fn sources(self) -> [Source; 2] {
+        match self {
+            Self::Musicbrainz => [Musicbrainz::ALBUM_SOURCE, Musicbrainz::TITLE_SOURCE],
+            Self::YoutubeMus => [YoutubeMus::ALBUM_SOURCE, YoutubeMus::TITLE_SOURCE],
+            Self::Youtube => [Youtube::ALBUM_SOURCE, Youtube::TITLE_SOURCE],
+            Self::Bandcamp => [Bandcamp::ALBUM_SOURCE, Bandcamp::TITLE_SOURCE],
+            Self::Qobuz => [Qobuz::ALBUM_SOURCE, Qobuz::TITLE_SOURCE],
+            Self::LastFm => [LastFm::ALBUM_SOURCE, LastFm::TITLE_SOURCE],
+            Self::Deezer => [Deezer::ALBUM_SOURCE, Deezer::TITLE_SOURCE],
+            Self::Itunes => [Itunes::ALBUM_SOURCE, Itunes::TITLE_SOURCE],
+            Self::Discogs => [Discogs::ALBUM_SOURCE, Discogs::TITLE_SOURCE],
+        }
+    }
+    /// Placeholder for the key input, `None` if source works without one
+    pub fn key_label(self) -> Option<&'static str> {
+        match self {
+            Self::LastFm => Some("api key"),
+            Self::Discogs => Some("personal access token"),
+            _ => None,
+        }
+    }
+}
+
+/// User supplied credentials, empty string is no key
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeys {
+    pub lastfm: String,
+    pub discogs: String,
+}
+impl ApiKeys {
+    pub fn get(&self, kind: SourceKind) -> Option<&String> {
+        match kind {
+            SourceKind::LastFm => Some(&self.lastfm),
+            SourceKind::Discogs => Some(&self.discogs),
+            _ => None,
+        }
+    }
+    pub fn set(&mut self, kind: SourceKind, key: String) {
+        match kind {
+            SourceKind::LastFm => self.lastfm = key,
+            SourceKind::Discogs => self.discogs = key,
+            _ => (),
+        }
+    }
+    /// Sources with a key label can not run without a key
+    pub fn is_ready(&self, kind: SourceKind) -> bool {
+        self.get(kind).is_none_or(|key| !key.trim().is_empty())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SourceEntry {
+    pub kind: SourceKind,
+    pub enabled: bool,
+}
+
+/// Sources that can be turned on and off from settings
+#[derive(Clone, Debug)]
+pub struct SourceRegistry {
+    entries: Vec<SourceEntry>,
+    pub keys: ApiKeys,
+}
+impl Default for SourceRegistry {
+    fn default() -> Self {
+        Self {
+            entries: SourceKind::ALL
+                .iter()
+                .map(|kind| SourceEntry {
+                    kind: *kind,
+                    enabled: true,
+                })
+                .collect(),
+            keys: ApiKeys::default(),
+        }
+    }
+}
+impl SourceRegistry {
+    pub fn entries(&self) -> &[SourceEntry] {
+        &self.entries
+    }
+    pub fn toggle(&mut self, kind: SourceKind) {
+        for entry in &mut self.entries {
+            if entry.kind == kind {
+                entry.enabled = !entry.enabled;
+            }
+        }
+    }
+    pub fn from_disabled(disabled: &[String], keys: ApiKeys) -> Self {
+        let mut this = Self {
+            keys,
+            ..Default::default()
+        };
+        for entry in &mut this.entries {
+            entry.enabled = !disabled.iter().any(|d| d == entry.kind.to_str());
+        }
+        this
+    }
+    pub fn disabled(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| !e.enabled)
+            .map(|e| e.kind.to_str().to_string())
+            .collect()
+    }
+    /// Sources that will be spawned by a queue
+    pub fn enabled(&self) -> Vec<SourceKind> {
+        self.entries
+            .iter()
+            .filter(|e| e.enabled && self.keys.is_ready(e.kind))
+            .map(|e| e.kind)
+            .collect()
+    }
+}
@@ -4,21 +4,31 @@ use anyhow::{Error, bail};
 use bytes::Bytes;
 use iced::futures::channel::mpsc::Sender;
 use log::{info, warn};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
+use serde::{Serialize, de::DeserializeOwned};
 use tokio::task::yield_now;
 
 use crate::{
-    api::queue::{QueueMessage, Source, TagsInput},
-    app::{iced_app::Message, img::SongImg, tags::Tags},
+    api::{
+        cache, net, probe,
+        queue::{QueueMessage, Source, TagsInput},
+        registry::ApiKeys,
+    },
+    app::{
+        iced_app::Message,
+        img::{ImageProgress, SongImg},
+        tags::Tags,
+    },
 };
 
 pub trait WebSource {
     const ALBUM_SOURCE: Source;
     const TITLE_SOURCE: Source;
-    async fn init(tags: TagsInput, tx: Sender<Message>) -> Result<(), Error>;
+    async fn init(tags: TagsInput, tx: Sender<Message>, keys: ApiKeys) -> Result<(), Error>;
     fn tags_ref(&self) -> &TagsInput;
     fn tx_ref(&self) -> &Sender<Message>;
     fn tx_clone(&self) -> Sender<Message>;
+    fn client_ref(&self) -> &Client;
     fn build_title_pompt(&self, title: &str, artist: &str) -> String;
     fn build_album_pompt(&self, album: &str, artist: &str) -> String;
     async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error>;
@@ -64,24 +74,44 @@ pub async fn send_message_from_source<T: WebSource>(src: &T, mes: QueueMessage)
     send_message(src.tags_ref(), &mut src.tx_clone(), mes).await;
 }
 
+/// Previews are shown right away, their full resolution follows once probed
+/// so they are ranked before anyone downloads them
 pub async fn send_song<T: WebSource>(src: &T, img: SongImg) {
-    send_message_from_source(src, QueueMessage::GotArt(img)).await
+    let urls = match &img.image {
+        ImageProgress::RawPreview(urls, _) if img.orig_res.is_none() => Some(urls.clone()),
+        _ => None,
+    };
+    send_message_from_source(src, QueueMessage::GotArt(img)).await;
+    if let Some(urls) = urls
+        && let Some(res) = probe::resolution(src.client_ref(), &urls).await
+    {
+        let mes = QueueMessage::Resolution(urls[0].clone(), res);
+        send_message_from_source(src, mes).await;
+    }
 }
 pub async fn get_img(client: &Client, urls: Vec<String>) -> Result<Bytes, Error> {
+    Ok(get_img_from(client, urls).await?.0)
+}
+/// First of `urls` that returns an image, with that url
+pub async fn get_img_from(client: &Client, urls: Vec<String>) -> Result<(Bytes, String), Error> {
     let mut last_error = None;
-    for url in &urls {
+    for url in urls {
+        if let Some(pic) = cache::get(&url, cache::IMG_TTL).await {
+            return Ok((pic, url));
+        }
         info!("Trying to get img: {}", url);
-        let req = client.get(url).build()?;
 
-        match client.execute(req).await {
-            Ok(response) => {
-                info!("Getting img body, url: {}", response.url());
-                let pic = response.bytes().await?;
+        match net::fetch(client.get(&url)).await {
+            Ok((status, pic)) => {
+                let success = status.is_success();
                 if pic.len() == 1097 {
                     last_error = Some(anyhow::Error::msg("\"No image\" received"));
                     continue;
                 }
-                return Ok(pic);
+                if success {
+                    cache::put(&url, &pic).await;
+                }
+                return Ok((pic, url));
             }
             Err(e) => {
                 warn!("failed to get img {url}");
@@ -93,6 +123,38 @@ pub async fn get_img(client: &Client, urls: Vec<String>) -> Result<Bytes, Error>
     bail!(last_error.unwrap())
 }
 
+/// Results of a search sent by another crate, cached as json under `key`
+pub async fn cached_results<T, F>(key: &str, search: F) -> Result<T, Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = Result<T, Error>>,
+{
+    if let Some(body) = cache::get(key, cache::PAGE_TTL).await
+        && let Ok(results) = serde_json::from_slice(&body)
+    {
+        return Ok(results);
+    }
+    let results = search.await?;
+    cache::put(key, &Bytes::from(serde_json::to_vec(&results)?)).await;
+    Ok(results)
+}
+
+/// Search page or api response body, cached by url
+pub async fn get_page(req: RequestBuilder) -> Result<String, Error> {
+    let (client, req) = req.build_split();
+    let req = req?;
+    let url = req.url().to_string();
+    if let Some(page) = cache::get(&url, cache::PAGE_TTL).await {
+        return Ok(String::from_utf8_lossy(&page).to_string());
+    }
+
+    let (status, page) = net::fetch(RequestBuilder::from_parts(client, req)).await?;
+    if status.is_success() {
+        cache::put(&url, &page).await;
+    }
+    Ok(String::from_utf8_lossy(&page).to_string())
+}
+
 pub fn filter_for_query(string: &str) -> String {
     string
         .chars()
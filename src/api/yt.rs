@@ -2,14 +2,17 @@ use anyhow::{Error, bail};
 use iced::futures::channel::mpsc::Sender;
 use log::warn;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use yt_search::{SearchFilters, YouTubeSearch};
 
 use crate::{
     api::{
+        net,
         queue::{
             Source::{self, *},
             TagsInput,
         },
+        registry::ApiKeys,
         shared::{self, WebSource, send_song},
     },
     app::{
@@ -24,6 +27,15 @@ pub struct Youtube {
     client: Client,
 }
 
+/// Search result, kept in the cache
+#[derive(Serialize, Deserialize)]
+struct Video {
+    id: String,
+    title: String,
+    channel: String,
+    duration: String,
+}
+
 const SEARCH_LIMIT: usize = 20;
 impl WebSource for Youtube {
     fn build_title_pompt(&self, title: &str, artist: &str) -> String {
@@ -45,31 +57,22 @@ impl WebSource for Youtube {
     fn tx_clone(&self) -> Sender<Message> {
         self.tx.clone()
     }
-    async fn init(tags: TagsInput, tx: Sender<Message>) -> Result<(), Error> {
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
         let this = Self {
             tags,
             tx,
-            client: Client::new(),
+            client: net::client(),
         };
         shared::init_source(this).await?;
         Ok(())
     }
 
     async fn with_prompt(&self, prompt: &str, src: Source) -> Result<(), Error> {
-        let search = match YouTubeSearch::new(None, false) {
-            Ok(search) => search,
-            Err(e) => {
-                bail!("Failed to initialize YouTubeSearch: {}", e);
-            }
-        };
-        let filters = SearchFilters {
-            sort_by: None,
-            duration: None,
-        };
-
-        let results = search.search(prompt, filters).await.inspect_err(|e| {
-            warn!("search failed {prompt}, {e}");
-        })?;
+        let key = format!("youtube search: {prompt}");
+        let results = shared::cached_results(&key, Self::search(prompt)).await?;
         let mut limit = SEARCH_LIMIT;
         let mut i = 0;
         while i < limit && i < results.len() {
@@ -78,9 +81,9 @@ impl WebSource for Youtube {
             {
                 let _ = self
                     .get_img(
-                        results[i].video_id.clone(),
+                        results[i].id.clone(),
                         results[i].title.clone(),
-                        results[i].channel_name.clone(),
+                        results[i].channel.clone(),
                         src,
                     )
                     .await;
@@ -95,6 +98,32 @@ impl WebSource for Youtube {
 }
 
 impl Youtube {
+    async fn search(prompt: &str) -> Result<Vec<Video>, Error> {
+        // yt-search has its own client, searches skip `net` limits, thumbnails do not
+        let search = match YouTubeSearch::new(None, false) {
+            Ok(search) => search,
+            Err(e) => {
+                bail!("Failed to initialize YouTubeSearch: {}", e);
+            }
+        };
+        let filters = SearchFilters {
+            sort_by: None,
+            duration: None,
+        };
+
+        let results = search.search(prompt, filters).await.inspect_err(|e| {
+            warn!("search failed {prompt}, {e}");
+        })?;
+        Ok(results
+            .into_iter()
+            .map(|r| Video {
+                id: r.video_id,
+                title: r.title,
+                channel: r.channel_name,
+                duration: r.duration,
+            })
+            .collect())
+    }
     async fn get_img(
         &self,
         link_id: String,
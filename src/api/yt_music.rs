@@ -8,10 +8,12 @@ use reqwest::Client;
 
 use crate::{
     api::{
+        net,
         queue::{
             Source::{self, *},
             TagsInput,
         },
+        registry::ApiKeys,
         shared::{self, WebSource, send_song},
     },
     app::{
@@ -46,11 +48,14 @@ impl WebSource for YoutubeMus {
     fn tx_clone(&self) -> Sender<Message> {
         self.tx.clone()
     }
-    async fn init(tags: TagsInput, tx: Sender<Message>) -> Result<(), Error> {
+    fn client_ref(&self) -> &Client {
+        &self.client
+    }
+    async fn init(tags: TagsInput, tx: Sender<Message>, _keys: ApiKeys) -> Result<(), Error> {
         let this = Self {
             tags,
             tx,
-            client: Client::new(),
+            client: net::client(),
         };
         shared::init_source(this).await?;
         Ok(())
@@ -60,17 +65,11 @@ impl WebSource for YoutubeMus {
 
         info!("Fetching youtube music search: {}", search_url);
 
-        let search_results_html = self
-            .client
-            .get(&search_url)
-            .header(
-                "User-Agent",
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:81.0) Gecko/20100101 Firefox/81.0",
-            )
-            .send()
-            .await?
-            .text()
-            .await?;
+        let search_results_html = shared::get_page(self.client.get(&search_url).header(
+            "User-Agent",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:81.0) Gecko/20100101 Firefox/81.0",
+        ))
+        .await?;
 
         let re = Regex::new(
             r#"\\x22text\\x22:\\x22([^\\]+?)\\x22,\\x22navigationEndpoint.*?\\x22videoId\\x22:\\x22([A-Za-z0-9_-]{11})\\x22"#,
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::{
+    app::song::{Song, SongId},
+    parser::file_parser::TagData,
+};
+
+pub type AlbumId = usize;
+/// Album artist or artist, then album, trimmed and lowercase
+pub type AlbumKey = (String, String);
+
+/// Tracks that share one cover
+/// * `members`: first one is the leader, it runs the queue and shows the image row
+#[derive(Debug, Clone)]
+pub struct Album {
+    pub members: Vec<SongId>,
+}
+impl Album {
+    pub fn leader(&self) -> Option<SongId> {
+        self.members.first().copied()
+    }
+    pub fn followers(&self) -> &[SongId] {
+        self.members.get(1..).unwrap_or_default()
+    }
+}
+
+pub fn album_key(tag_data: &TagData) -> Option<AlbumKey> {
+    let artist = tag_data
+        .file
+        .album_artist()
+        .map(str::to_string)
+        .or(tag_data.artist.clone())?;
+    let album = tag_data.album.as_ref()?;
+    let norm = |s: &str| s.trim().to_lowercase();
+    if norm(album).is_empty() {
+        return None;
+    }
+    Some((norm(&artist), norm(album)))
+}
+/// Indices with the same key in order of appearance, albums of a single track are left out
+fn group_keys(keys: &[Option<AlbumKey>]) -> Vec<Vec<usize>> {
+    let mut found: HashMap<&AlbumKey, usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        let Some(key) = key else {
+            continue;
+        };
+        match found.get(key) {
+            Some(g) => groups[*g].push(i),
+            None => {
+                found.insert(key, groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+    groups.retain(|g| g.len() > 1);
+    groups
+}
+
+/// Group songs pushed after `from` into new albums
+pub fn group_songs(songs: &mut [Song], from: SongId, albums: &mut Vec<Album>) {
+    let keys: Vec<_> = songs[from..]
+        .iter()
+        .map(|s| album_key(&s.tag_data))
+        .collect();
+    for group in group_keys(&keys) {
+        let album_id = albums.len();
+        let members: Vec<SongId> = group.into_iter().map(|i| i + from).collect();
+        for id in &members {
+            songs[*id].album = Some(album_id);
+        }
+        albums.push(Album { members });
+    }
+}
+/// Leader of the album, if `id` only follows it
+pub fn follows(songs: &[Song], albums: &[Album], id: SongId) -> Option<SongId> {
+    let leader = albums[songs[id].album?].leader()?;
+    (leader != id).then_some(leader)
+}
+/// Take the song out of its album, the next track leads when the leader leaves
+pub fn detach(songs: &mut [Song], albums: &mut [Album], id: SongId) {
+    let Some(album_id) = songs[id].album.take() else {
+        return;
+    };
+    let album = &mut albums[album_id];
+    album.members.retain(|m| *m != id);
+    // one track left is not an album anymore
+    if album.members.len() == 1 {
+        songs[album.members[0]].album = None;
+        album.members.clear();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::app::album::group_keys;
+
+    #[test]
+    fn groups_by_key() {
+        let key = |a: &str, b: &str| Some((a.to_string(), b.to_string()));
+        let keys = vec![
+            key("daft punk", "discovery"),
+            None,
+            key("daft punk", "homework"),
+            key("daft punk", "discovery"),
+            key("air", "moon safari"),
+            key("daft punk", "homework"),
+            key("daft punk", "discovery"),
+        ];
+        assert_eq!(group_keys(&keys), vec![vec![0, 3, 6], vec![2, 5]]);
+        assert!(group_keys(&[key("air", "moon safari"), None]).is_empty());
+    }
+}
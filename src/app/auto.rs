@@ -0,0 +1,208 @@
+//! Confidence in the top pick of a song, auto mode applies only confident picks
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::registry::SourceKind,
+    app::{
+        img::{ImgId, SongImg},
+        song::Song,
+        tags::{TagType, Tags},
+    },
+};
+
+/// Points of each part, 100 in total
+const GROUP_PTS: u32 = 30;
+const AGREEMENT_PTS: u32 = 25;
+const RES_PTS: u32 = 25;
+const TAG_PTS: u32 = 20;
+/// Top group this large gets all group points
+const GROUP_FULL: u32 = 4;
+/// Shorter side that gets all resolution points
+const RES_FULL: u32 = 1000;
+
+/// * `threshold`: lowest confidence in percent that is applied without review
+/// * `limit`: songs searched at once
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoSettings {
+    pub threshold: u32,
+    pub limit: usize,
+}
+impl Default for AutoSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 70,
+            limit: 3,
+        }
+    }
+}
+
+/// Confidence in percent that `img_id`, the image that will be written, is right:
+/// * group size: how many results are the same picture
+/// * source agreement: share of the searched sources that found this picture,
+///   a lone result of a single source gets little
+/// * resolution: of the picked image, half the points when unknown
+/// * tag match: title, album and artist of the file that the sources found too
+pub fn confidence(song: &Song, img_id: ImgId) -> u32 {
+    let Some(group_id) = song.img_groups.group_of(img_id) else {
+        return 0;
+    };
+    let data = &song.tag_data;
+    let own = [
+        (TagType::Title, data.title.as_deref()),
+        (TagType::Album, data.album.as_deref()),
+        (TagType::Artist, data.artist.as_deref()),
+    ];
+    let searched = song.sources_finished.1.max(0) as usize;
+    image_points(
+        &song.imgs,
+        song.img_groups.group(group_id),
+        img_id,
+        searched,
+    ) + tag_points(&own, &song.new_tags)
+}
+/// * `group`: the group of `img_id`
+/// * `searched`: sources that were asked, local files come on top
+fn image_points(imgs: &[SongImg], group: &[ImgId], img_id: ImgId, searched: usize) -> u32 {
+    let top = &imgs[img_id];
+
+    let size = (group.len() as u32).min(GROUP_FULL);
+    let group_pts = GROUP_PTS * size / GROUP_FULL;
+
+    // album and title searches of one site are one source
+    let found: HashSet<_> = imgs.iter().map(|img| SourceKind::of(img.src)).collect();
+    let agreeing: HashSet<_> = group.iter().map(|i| SourceKind::of(imgs[*i].src)).collect();
+    let sources = searched + found.contains(&None) as usize;
+    let agreement_pts = AGREEMENT_PTS * agreeing.len() as u32 / sources.max(found.len()) as u32;
+
+    let res_pts = match top.orig_res {
+        Some((w, h)) => RES_PTS * w.min(h).min(RES_FULL) / RES_FULL,
+        None => RES_PTS / 2,
+    };
+    group_pts + agreement_pts + res_pts
+}
+fn tag_points(own: &[(TagType, Option<&str>)], found: &Tags) -> u32 {
+    let own: Vec<_> = own
+        .iter()
+        .filter_map(|(key, value)| value.map(|v| (*key, v.trim().to_lowercase())))
+        .collect();
+    if own.is_empty() {
+        return TAG_PTS / 2;
+    }
+    let matched = own
+        .iter()
+        .filter(|(key, value)| {
+            found
+                .sorted
+                .iter()
+                .any(|tag| tag.key == *key && tag.value.trim().to_lowercase() == *value)
+        })
+        .count();
+    TAG_PTS * matched as u32 / own.len() as u32
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor, sync::Arc};
+
+    use bytes::Bytes;
+    use image::ImageFormat;
+    use tokio::{runtime::Runtime, sync::Semaphore};
+
+    use crate::{
+        api::queue::Source::{self, DeezerAlbum, ItunesAlbum, YoutubeTitle},
+        app::{
+            auto::{AutoSettings, TAG_PTS, confidence, image_points, tag_points},
+            img::{ImageProgress::Raw, ImgFormat::Png, SongImg},
+            img_group::ImgGroups,
+            phash::tests::cover,
+            tags::{Tag, TagType, Tags},
+        },
+        parser::file_parser::parse_file,
+    };
+
+    fn decoded(covers: &[(u32, u32, Source)]) -> (ImgGroups, Vec<SongImg>) {
+        let sem = Arc::new(Semaphore::new(1));
+        let rt = Runtime::new().unwrap();
+        let (mut groups, mut imgs) = (ImgGroups::new(), Vec::new());
+        for (seed, size, src) in covers {
+            let mut bytes = Vec::new();
+            cover(*seed, *size)
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .unwrap();
+            let img = SongImg::new(Png, Raw(Bytes::from(bytes)), *src, String::new());
+            let img = rt.block_on(img.decode_and_sample(sem.clone())).unwrap();
+            img.push_and_group(&mut groups, &mut imgs).unwrap();
+        }
+        (groups, imgs)
+    }
+    fn points(covers: &[(u32, u32, Source)], searched: usize) -> u32 {
+        let (groups, imgs) = decoded(covers);
+        let top = groups.first_in_first_group();
+        image_points(&imgs, groups.group(0), top, searched)
+    }
+
+    #[test]
+    fn agreeing_sources_confident() {
+        let agreed = points(
+            &[
+                (1, 1200, DeezerAlbum),
+                (1, 600, ItunesAlbum),
+                (1, 300, YoutubeTitle),
+                (2, 300, YoutubeTitle),
+            ],
+            3,
+        );
+        let lone = points(&[(1, 300, YoutubeTitle), (2, 300, DeezerAlbum)], 2);
+        assert!(agreed >= 60, "{agreed}");
+        assert!(lone < 30, "{lone}");
+    }
+    #[test]
+    fn lone_result_reviewed() {
+        let dir = env::temp_dir().join(format!("mass_coverart_auto_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("track.mp3");
+        fs::write(&path, []).unwrap();
+        id3::Tag::new()
+            .write_to_path(&path, id3::Version::Id3v24)
+            .unwrap();
+        let mut song = parse_file(path, &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // the tags match and the image is large, but only one of nine sources found it
+        song.tag_data.title = Some("Title".to_string());
+        for key in [TagType::Title, TagType::Album, TagType::Artist] {
+            song.new_tags.add(Tag {
+                score: 1,
+                key,
+                value: "Title".to_string(),
+            });
+        }
+        song.tag_data.album = song.tag_data.title.clone();
+        song.tag_data.artist = song.tag_data.title.clone();
+        (song.img_groups, song.imgs) = decoded(&[(1, 1200, DeezerAlbum)]);
+        song.sources_finished = (9, 9);
+
+        let lone = confidence(&song, 0);
+        assert!(lone < AutoSettings::default().threshold, "{lone}");
+        assert_eq!(confidence(&song, 1), 0);
+    }
+    #[test]
+    fn tags_matched() {
+        let mut found = Tags::new();
+        found.add(Tag {
+            score: 1,
+            key: TagType::Album,
+            value: "Album ".to_string(),
+        });
+        let own = [
+            (TagType::Title, None),
+            (TagType::Album, Some("album")),
+            (TagType::Artist, Some("artist")),
+        ];
+        assert_eq!(tag_points(&own, &found), TAG_PTS / 2);
+        assert_eq!(tag_points(&own[..2], &found), TAG_PTS);
+        assert_eq!(tag_points(&[], &found), TAG_PTS / 2);
+    }
+}
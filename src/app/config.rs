@@ -0,0 +1,195 @@
+use std::{
+    env,
+    fs::{self, create_dir_all},
+    path::PathBuf,
+};
+
+use anyhow::{Error, anyhow};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    api::registry::{ApiKeys, SourceRegistry},
+    app::{auto::AutoSettings, img::ImageSettings, keys::KeyBindings, weights::SourceWeights},
+    parser::{
+        atomic,
+        file_parser::{ApplySettings, ParseSettings},
+    },
+};
+
+/// Bump on breaking changes and upgrade older files in `migrate`
+const CONFIG_VERSION: u64 = 2;
+const CONFIG_FILE: &str = "config.json";
+const APP_DIR: &str = "mass_coverart";
+
+/// Settings that survive restarts
+/// * `disabled_sources`: stored by name so new sources are enabled by default
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub version: u64,
+    pub parse_settings: ParseSettings,
+    pub img_settings: ImageSettings,
+    pub apply_settings: ApplySettings,
+    pub auto_mod: bool,
+    pub auto_settings: AutoSettings,
+    pub keep_backup: bool,
+    pub source_weights: SourceWeights,
+    pub keybindings: KeyBindings,
+    pub disabled_sources: Vec<String>,
+    pub api_keys: ApiKeys,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            parse_settings: ParseSettings::default(),
+            img_settings: ImageSettings::default(),
+            apply_settings: ApplySettings::default(),
+            auto_mod: false,
+            auto_settings: AutoSettings::default(),
+            keep_backup: false,
+            source_weights: SourceWeights::default(),
+            keybindings: KeyBindings::default(),
+            disabled_sources: Vec::new(),
+            api_keys: ApiKeys::default(),
+        }
+    }
+}
+impl Config {
+    /// Never fails, falls back to defaults
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(Some(config)) => config,
+            Ok(None) => {
+                info!("no config file, using defaults");
+                Self::default()
+            }
+            Err(e) => {
+                warn!("config was not loaded: {e}");
+                Self::default()
+            }
+        }
+    }
+    fn try_load() -> Result<Option<Self>, Error> {
+        let path = config_path().ok_or(anyhow!("config dir is unknown"))?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let config = Self::parse(&fs::read(&path)?)?;
+        info!("config loaded from {}", path.display());
+        Ok(Some(config))
+    }
+    fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut value: Value = serde_json::from_slice(bytes)?;
+        migrate(&mut value);
+        let mut config: Self = serde_json::from_value(value)?;
+        config.version = CONFIG_VERSION;
+
+        let parse = &mut config.parse_settings;
+        if parse.reg_keys.is_empty() || parse.reg_keys.len() != parse.reg_separators.len() + 1 {
+            warn!("invalid file name pattern in config, using default");
+            let default = ParseSettings::default();
+            parse.reg_keys = default.reg_keys;
+            parse.reg_separators = default.reg_separators;
+        }
+        Ok(config)
+    }
+    pub fn save(&self) {
+        let _ = self
+            .try_save()
+            .inspect_err(|e| warn!("config was not saved: {e}"));
+    }
+    fn try_save(&self) -> Result<(), Error> {
+        let path = config_path().ok_or(anyhow!("config dir is unknown"))?;
+        create_dir_all(path.parent().expect("config file has parent"))?;
+        atomic::write_bytes(&path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn sources(&self) -> SourceRegistry {
+        SourceRegistry::from_disabled(&self.disabled_sources, self.api_keys.clone())
+    }
+}
+
+/// Upgrade json written by an older version in place, every written file has a version
+fn migrate(value: &mut Value) {
+    let Some(version) = value.get("version").and_then(Value::as_u64) else {
+        return;
+    };
+    if version > CONFIG_VERSION {
+        warn!("config was written by a newer version {version}, unknown fields are ignored");
+    }
+    // version 1 had a jpeg toggle instead of the output format
+    if version < 2
+        && let Some(img) = value.get_mut("img_settings").and_then(Value::as_object_mut)
+        && img.remove("jpg").and_then(|jpg| jpg.as_bool()) == Some(false)
+    {
+        img.insert("format".to_string(), Value::from("Original"));
+    }
+}
+
+/// Platform config directory of the app
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    };
+    base.map(|b| b.join(APP_DIR))
+}
+/// Platform cache directory of the app
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+    };
+    base.map(|b| b.join(APP_DIR))
+}
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(CONFIG_FILE))
+}
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        config::{CONFIG_VERSION, Config},
+        encode::OutputFormat,
+    };
+
+    #[test]
+    fn partial_file_loads() {
+        let old = br#"{"img_settings": {"downscale": 500}, "disabled_sources": ["youtube.com"]}"#;
+        let config = Config::parse(old).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.img_settings.downscale, 500);
+        assert!(config.img_settings.square);
+        assert_eq!(config.sources().disabled(), vec!["youtube.com".to_string()]);
+    }
+    #[test]
+    fn jpg_toggle_migrated() {
+        let v1 = br#"{"version": 1, "img_settings": {"jpg": false}}"#;
+        let config = Config::parse(v1).unwrap();
+        assert_eq!(config.img_settings.format, OutputFormat::Original);
+        let v1 = br#"{"version": 1, "img_settings": {"jpg": true}}"#;
+        assert_eq!(
+            Config::parse(v1).unwrap().img_settings.format,
+            OutputFormat::Jpeg
+        );
+    }
+    #[test]
+    fn broken_pattern_reset() {
+        let broken = br#"{"parse_settings": {"reg_keys": ["Album"], "reg_separators": [" - "]}}"#;
+        let config = Config::parse(broken).unwrap();
+        let set = config.parse_settings;
+        assert_eq!(set.reg_keys.len(), set.reg_separators.len() + 1);
+    }
+}
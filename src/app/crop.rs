@@ -0,0 +1,326 @@
+//! Letterbox trimming and squaring of covers that are not square
+use image::{
+    DynamicImage, GenericImageView, Rgb, Rgba, RgbaImage,
+    imageops::{self, FilterType::Triangle},
+};
+use serde::{Deserialize, Serialize};
+
+/// Channel difference still counted as the border color, jpeg noise included
+const BORDER_TOLERANCE: u8 = 24;
+/// Pixels of a border line that may differ, channel logos in the bars
+const BORDER_OUTLIERS: f32 = 0.02;
+/// Borders darker than this are bars even on all four sides
+const DARK_BORDER: u8 = 40;
+/// Part of each side left after trimming, less means the picture itself is plain
+const MIN_CONTENT: f32 = 0.3;
+/// Blurred background is made this small first, then scaled up
+const BLUR_DIM: u32 = 64;
+/// Smallest crop side, as part of the shorter image side
+const MIN_CROP: f32 = 0.1;
+
+/// How a non square image is made square
+/// * `Blur`: pad with a blurred, stretched copy of the image
+/// * `Solid`: pad with the average color
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SquareFill {
+    #[default]
+    Crop,
+    Blur,
+    Solid,
+}
+impl SquareFill {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Crop => "crop",
+            Self::Blur => "blur",
+            Self::Solid => "solid",
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Self::Crop => Self::Blur,
+            Self::Blur => Self::Solid,
+            Self::Solid => Self::Crop,
+        }
+    }
+}
+
+fn close(a: &Rgb<u8>, b: &Rgb<u8>) -> bool {
+    a.0.iter()
+        .zip(b.0)
+        .all(|(x, y)| x.abs_diff(y) <= BORDER_TOLERANCE)
+}
+/// Line of pixels is a border if nearly all of it has the `border` color
+fn is_border<'a>(line: impl Iterator<Item = &'a Rgb<u8>>, border: &Rgb<u8>, len: u32) -> bool {
+    let differ = line.filter(|p| !close(p, border)).count();
+    differ as f32 <= len as f32 * BORDER_OUTLIERS
+}
+/// Cut uniform bars off opposite sides, letterbox or pillarbox.
+/// Frames of one light color on all four sides are part of the art and stay
+pub fn trim_borders(img: &DynamicImage) -> DynamicImage {
+    let rgb = img.to_rgb8();
+    let (w, h) = rgb.dimensions();
+    if w < 3 || h < 3 {
+        return img.clone();
+    }
+    let border = *rgb.get_pixel(0, 0);
+    let row = |y: u32| is_border((0..w).map(|x| rgb.get_pixel(x, y)), &border, w);
+    let col = |x: u32| is_border((0..h).map(|y| rgb.get_pixel(x, y)), &border, h);
+
+    let top = (0..h).take_while(|y| row(*y)).count() as u32;
+    if top == h {
+        return img.clone();
+    }
+    let bottom = (0..h).rev().take_while(|y| row(*y)).count() as u32;
+    let left = (0..w).take_while(|x| col(*x)).count() as u32;
+    let right = (0..w).rev().take_while(|x| col(*x)).count() as u32;
+
+    // every line of one axis can pass as border when the art is a thin stripe across it
+    let rows = top > 0 && bottom > 0 && top + bottom < h;
+    let cols = left > 0 && right > 0 && left + right < w;
+    let dark = border.0.iter().all(|c| *c < DARK_BORDER);
+    let (rows, cols) = if rows && cols && !dark {
+        (false, false)
+    } else {
+        (rows, cols)
+    };
+    let (y, new_h) = if rows {
+        (top, h - top - bottom)
+    } else {
+        (0, h)
+    };
+    let (x, new_w) = if cols {
+        (left, w - left - right)
+    } else {
+        (0, w)
+    };
+    if (new_w as f32) < w as f32 * MIN_CONTENT || (new_h as f32) < h as f32 * MIN_CONTENT {
+        return img.clone();
+    }
+    img.crop_imm(x, y, new_w, new_h)
+}
+/// Largest centered square, fine for portrait images too
+pub fn center_square(img: &DynamicImage) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    let side = w.min(h);
+    img.crop_imm((w - side) / 2, (h - side) / 2, side, side)
+}
+pub fn to_square(img: DynamicImage, fill: SquareFill) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    if w == h {
+        return img;
+    }
+    let side = w.max(h);
+    let background = match fill {
+        SquareFill::Crop => return center_square(&img),
+        SquareFill::Blur => img
+            .resize_to_fill(BLUR_DIM, BLUR_DIM, Triangle)
+            .blur(BLUR_DIM as f32 / 16.0)
+            .resize_exact(side, side, Triangle)
+            .into_rgba8(),
+        SquareFill::Solid => {
+            let avg = img.thumbnail_exact(1, 1).to_rgba8();
+            let [r, g, b, _] = avg.get_pixel(0, 0).0;
+            RgbaImage::from_pixel(side, side, Rgba([r, g, b, 255]))
+        }
+    };
+    let mut square = background;
+    imageops::overlay(
+        &mut square,
+        &img.to_rgba8(),
+        ((side - w) / 2) as i64,
+        ((side - h) / 2) as i64,
+    );
+    let square = DynamicImage::ImageRgba8(square);
+    if img.color().has_alpha() {
+        square
+    } else {
+        DynamicImage::ImageRgb8(square.into_rgb8())
+    }
+}
+
+/// Square crop picked in the editor, kept in fractions so it fits any decode of the image
+/// * `cx`, `cy`: center, part of the width and height
+/// * `side`: part of the shorter side
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CropRect {
+    pub cx: f32,
+    pub cy: f32,
+    pub side: f32,
+}
+impl Default for CropRect {
+    fn default() -> Self {
+        Self {
+            cx: 0.5,
+            cy: 0.5,
+            side: 1.0,
+        }
+    }
+}
+impl CropRect {
+    /// `x`, `y` and side in px of a `w`x`h` image, always inside it
+    pub fn pixels(&self, (w, h): (u32, u32)) -> (u32, u32, u32) {
+        let short = w.min(h);
+        let side = ((self.side * short as f32).round() as u32).clamp(1, short.max(1));
+        let x = (self.cx * w as f32 - side as f32 / 2.0).round().max(0.0) as u32;
+        let y = (self.cy * h as f32 - side as f32 / 2.0).round().max(0.0) as u32;
+        (x.min(w - side), y.min(h - side), side)
+    }
+    /// Keep the whole square inside a `w`x`h` image
+    fn clamp(&mut self, (w, h): (u32, u32)) {
+        self.side = self.side.clamp(MIN_CROP, 1.0);
+        let short = w.min(h) as f32;
+        let half_x = self.side * short / w as f32 / 2.0;
+        let half_y = self.side * short / h as f32 / 2.0;
+        self.cx = self.cx.clamp(half_x, 1.0 - half_x);
+        self.cy = self.cy.clamp(half_y, 1.0 - half_y);
+    }
+}
+
+/// Manual changes from the preview editor, applied before the image settings
+/// * `turns`: clockwise quarter turns
+/// * `crop`: replaces trimming and squaring when set, relative to the turned and flipped image
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImgEdit {
+    pub turns: u8,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub crop: Option<CropRect>,
+}
+impl ImgEdit {
+    /// The crop is dropped, its fractions mean something else after a turn
+    pub fn rotate(&mut self) {
+        self.turns = (self.turns + 1) % 4;
+        self.crop = None;
+    }
+    pub fn flip(&mut self, horizontal: bool) {
+        if horizontal {
+            self.flip_h = !self.flip_h;
+        } else {
+            self.flip_v = !self.flip_v;
+        }
+        if let Some(crop) = &mut self.crop {
+            if horizontal {
+                crop.cx = 1.0 - crop.cx;
+            } else {
+                crop.cy = 1.0 - crop.cy;
+            }
+        }
+    }
+    /// Center the crop on `cx`, `cy` of the oriented image, `dims`
+    pub fn move_crop(&mut self, cx: f32, cy: f32, dims: (u32, u32)) {
+        let crop = self.crop.get_or_insert_default();
+        crop.cx = cx;
+        crop.cy = cy;
+        crop.clamp(dims);
+    }
+    pub fn nudge_crop(&mut self, dx: f32, dy: f32, dims: (u32, u32)) {
+        let crop = self.crop.unwrap_or_default();
+        self.move_crop(crop.cx + dx, crop.cy + dy, dims);
+    }
+    /// Grow or shrink the crop side by `factor`, around its center
+    pub fn resize_crop(&mut self, factor: f32, dims: (u32, u32)) {
+        let crop = self.crop.get_or_insert_default();
+        crop.side *= factor;
+        crop.clamp(dims);
+    }
+    /// Turned and flipped, not cropped
+    pub fn orient(&self, img: DynamicImage) -> DynamicImage {
+        let img = match self.turns {
+            1 => img.rotate90(),
+            2 => img.rotate180(),
+            3 => img.rotate270(),
+            _ => img,
+        };
+        let img = if self.flip_h { img.fliph() } else { img };
+        if self.flip_v { img.flipv() } else { img }
+    }
+    pub fn crop(&self, img: &DynamicImage) -> Option<DynamicImage> {
+        let (x, y, side) = self.crop?.pixels(img.dimensions());
+        Some(img.crop_imm(x, y, side, side))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    use crate::app::crop::{CropRect, ImgEdit, SquareFill, center_square, to_square, trim_borders};
+
+    /// Colored `content` in the middle of `bar` colored bars
+    fn boxed(w: u32, h: u32, content: (u32, u32), bar: [u8; 3]) -> DynamicImage {
+        let (x0, y0) = ((w - content.0) / 2, (h - content.1) / 2);
+        DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| {
+            let inside = x >= x0 && x < x0 + content.0 && y >= y0 && y < y0 + content.1;
+            if inside {
+                Rgb([(x * 7 % 200) as u8 + 50, 120, (y * 3 % 200) as u8 + 50])
+            } else {
+                Rgb(bar)
+            }
+        }))
+    }
+
+    #[test]
+    fn letterbox_trimmed() {
+        // 4:3 thumbnail of a 16:9 video of a square cover
+        let thumb = boxed(480, 360, (270, 270), [0, 0, 0]);
+        assert_eq!(trim_borders(&thumb).dimensions(), (270, 270));
+        let pillar = boxed(1280, 720, (720, 720), [2, 1, 3]);
+        assert_eq!(trim_borders(&pillar).dimensions(), (720, 720));
+    }
+    #[test]
+    fn art_kept() {
+        // white frame on every side is a design choice
+        let framed = boxed(600, 600, (500, 500), [250, 250, 250]);
+        assert_eq!(trim_borders(&framed).dimensions(), (600, 600));
+        let plain = boxed(300, 300, (0, 0), [0, 0, 0]);
+        assert_eq!(trim_borders(&plain).dimensions(), (300, 300));
+        let thin = boxed(600, 600, (600, 100), [0, 0, 0]);
+        assert_eq!(trim_borders(&thin).dimensions(), (600, 600));
+        // each column is a border with fewer outliers than allowed, each row is not
+        let stripe = boxed(200, 200, (200, 1), [0, 0, 0]);
+        assert_eq!(trim_borders(&stripe).dimensions(), (200, 200));
+        let stripe = boxed(200, 200, (1, 200), [0, 0, 0]);
+        assert_eq!(trim_borders(&stripe).dimensions(), (200, 200));
+    }
+    #[test]
+    fn portrait_square() {
+        let portrait = boxed(300, 500, (300, 500), [0, 0, 0]);
+        let cropped = center_square(&portrait);
+        assert_eq!(cropped.dimensions(), (300, 300));
+        assert_eq!(cropped.get_pixel(0, 0), portrait.get_pixel(0, 100));
+
+        for fill in [SquareFill::Blur, SquareFill::Solid] {
+            let padded = to_square(portrait.clone(), fill);
+            assert_eq!(padded.dimensions(), (500, 500));
+            assert_eq!(padded.get_pixel(100, 0), portrait.get_pixel(0, 0));
+            assert_eq!(padded.color(), portrait.color());
+        }
+        let wide = to_square(boxed(400, 100, (400, 100), [0, 0, 0]), SquareFill::Crop);
+        assert_eq!(wide.dimensions(), (100, 100));
+    }
+    #[test]
+    fn manual_edit() {
+        let img = boxed(400, 200, (400, 200), [0, 0, 0]);
+        let mut edit = ImgEdit::default();
+        edit.rotate();
+        let turned = edit.orient(img.clone());
+        assert_eq!(turned.dimensions(), (200, 400));
+        assert_eq!(turned.get_pixel(199, 0), img.get_pixel(0, 0));
+
+        // dragged past the bottom edge, stays inside
+        edit.move_crop(0.5, 1.0, turned.dimensions());
+        let crop = edit.crop.unwrap();
+        assert_eq!(crop.pixels(turned.dimensions()), (0, 200, 200));
+        edit.resize_crop(0.5, turned.dimensions());
+        edit.nudge_crop(-1.0, 0.0, turned.dimensions());
+        assert_eq!(edit.crop(&turned).unwrap().dimensions(), (100, 100));
+        assert_eq!(edit.crop.unwrap().pixels((200, 400)), (0, 250, 100));
+
+        edit.flip(true);
+        assert_eq!(edit.crop.unwrap().pixels((200, 400)), (100, 250, 100));
+        edit.rotate();
+        assert_eq!(edit.crop, None);
+        let side = CropRect::default().pixels((301, 300));
+        assert_eq!(side, (1, 0, 300));
+    }
+}
@@ -0,0 +1,222 @@
+//! Encoding of the final cover: format, jpeg quality, metadata and a size budget.
+//! Jpegs are always baseline, the encoder writes no progressive scans
+use std::io::{BufRead, Seek};
+
+use anyhow::Error;
+use image::{
+    DynamicImage, GenericImageView, ImageDecoder, ImageEncoder, ImageReader, ImageResult,
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilter, PngEncoder},
+    },
+    imageops::FilterType::Triangle,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::app::img::{ImageSettings, ImgFormat};
+
+/// Quality taken off per try when the cover is over the size budget
+const QUALITY_STEP: u8 = 10;
+/// Below this quality the cover is made smaller instead
+const MIN_QUALITY: u8 = 40;
+/// Part of each side kept per try once quality is at the minimum
+const SHRINK: f32 = 0.85;
+/// Smallest side the size budget may shrink the cover to
+const MIN_SIDE: u32 = 200;
+
+/// Format of the written cover, embedded and in the folder file
+/// * `Original`: format the image was downloaded in
+///
+/// WebP is not offered: `image` writes only lossless webp, many times the size of a jpeg cover,
+/// and few players read an embedded one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    Original,
+    #[default]
+    Jpeg,
+    Png,
+}
+impl OutputFormat {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Original => "original",
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Self::Original => Self::Jpeg,
+            Self::Jpeg => Self::Png,
+            Self::Png => Self::Original,
+        }
+    }
+    pub fn resolve(self, orig: ImgFormat) -> ImgFormat {
+        match self {
+            Self::Original => orig,
+            Self::Jpeg => ImgFormat::Jpeg,
+            Self::Png => ImgFormat::Png,
+        }
+    }
+}
+
+/// Color profile and exif of the downloaded file, written only when metadata is kept
+#[derive(Clone, Debug, Default)]
+pub struct ImgMeta {
+    pub icc: Option<Vec<u8>>,
+    pub exif: Option<Vec<u8>>,
+}
+
+/// Decode the image and read its metadata on the way
+pub fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> ImageResult<(DynamicImage, ImgMeta)> {
+    let mut decoder = reader.into_decoder()?;
+    let meta = ImgMeta {
+        icc: decoder.icc_profile().ok().flatten(),
+        exif: decoder.exif_metadata().ok().flatten(),
+    };
+    Ok((DynamicImage::from_decoder(decoder)?, meta))
+}
+fn add_meta(encoder: &mut impl ImageEncoder, meta: &ImgMeta) {
+    if let Some(icc) = &meta.icc {
+        let _ = encoder
+            .set_icc_profile(icc.clone())
+            .inspect_err(|e| warn!("color profile was not written: {e}"));
+    }
+    if let Some(exif) = &meta.exif {
+        let _ = encoder
+            .set_exif_metadata(exif.clone())
+            .inspect_err(|e| warn!("exif was not written: {e}"));
+    }
+}
+fn encode_once(
+    img: &DynamicImage,
+    format: ImgFormat,
+    quality: u8,
+    set: &ImageSettings,
+    meta: &ImgMeta,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    match format {
+        ImgFormat::Jpeg => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut bytes, quality);
+            if !set.strip_metadata {
+                add_meta(&mut encoder, meta);
+            }
+            // jpeg has no alpha channel
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        ImgFormat::Png => {
+            let compression = if set.optimize_png {
+                CompressionType::Best
+            } else {
+                CompressionType::Default
+            };
+            let mut encoder =
+                PngEncoder::new_with_quality(&mut bytes, compression, PngFilter::Adaptive);
+            if !set.strip_metadata {
+                add_meta(&mut encoder, meta);
+            }
+            img.write_with_encoder(encoder)?;
+        }
+    }
+    Ok(bytes)
+}
+/// Encode the cover, stepping jpeg quality down and then shrinking it until it fits `max_kb`.
+/// Returns the smallest try if nothing fits
+pub fn encode(
+    img: DynamicImage,
+    format: ImgFormat,
+    set: &ImageSettings,
+    meta: &ImgMeta,
+) -> Result<Vec<u8>, Error> {
+    let budget = set.max_kb as usize * 1024;
+    let mut img = img;
+    let mut quality = set.jpeg_quality.clamp(1, 100);
+    loop {
+        let bytes = encode_once(&img, format, quality, set, meta)?;
+        if budget == 0 || bytes.len() <= budget {
+            if budget != 0 {
+                let (w, h) = img.dimensions();
+                info!("cover fits {} KB at quality {quality}, {w}x{h}", set.max_kb);
+            }
+            return Ok(bytes);
+        }
+        let (w, h) = img.dimensions();
+        if matches!(format, ImgFormat::Jpeg) && quality > MIN_QUALITY {
+            quality = quality.saturating_sub(QUALITY_STEP).max(MIN_QUALITY);
+        } else if w.min(h) > MIN_SIDE {
+            let (w, h) = (w as f32 * SHRINK, h as f32 * SHRINK);
+            img = img.resize_exact(w as u32, h as u32, Triangle);
+        } else {
+            warn!(
+                "cover is {} KB, over the {} KB limit",
+                bytes.len() / 1024,
+                set.max_kb
+            );
+            return Ok(bytes);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{
+        DynamicImage, ImageEncoder, ImageReader, Rgb, RgbImage, codecs::jpeg::JpegEncoder,
+    };
+
+    use crate::app::{
+        encode::{ImgMeta, decode, encode},
+        img::{ImageSettings, ImgFormat},
+    };
+
+    /// Hard to compress image
+    fn noise(size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+            let v = (x.wrapping_mul(2654435761) ^ y.wrapping_mul(40503)).wrapping_mul(97);
+            Rgb([(v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+        }))
+    }
+    fn read(bytes: &[u8]) -> (DynamicImage, ImgMeta) {
+        let reader = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .unwrap();
+        decode(reader).unwrap()
+    }
+
+    #[test]
+    fn fits_size_budget() {
+        let img = noise(800);
+        let mut set = ImageSettings::default();
+        let full = encode(img.clone(), ImgFormat::Jpeg, &set, &ImgMeta::default()).unwrap();
+        assert!(full.len() > 150 * 1024);
+
+        set.max_kb = 150;
+        let fitted = encode(img.clone(), ImgFormat::Jpeg, &set, &ImgMeta::default()).unwrap();
+        assert!(fitted.len() <= 150 * 1024);
+        let png = encode(img, ImgFormat::Png, &set, &ImgMeta::default()).unwrap();
+        assert!(png.len() <= 150 * 1024);
+        assert!(read(&png).0.width() < 800);
+    }
+    #[test]
+    fn metadata_stripped() {
+        let icc = b"not really a profile".to_vec();
+        let mut bytes = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut bytes);
+        encoder.set_icc_profile(icc.clone()).unwrap();
+        noise(64).write_with_encoder(encoder).unwrap();
+
+        let (img, meta) = read(&bytes);
+        assert_eq!(meta.icc.as_ref(), Some(&icc));
+        let mut set = ImageSettings::default();
+        let stripped = encode(img.clone(), ImgFormat::Jpeg, &set, &meta).unwrap();
+        assert_eq!(read(&stripped).1.icc, None);
+
+        set.strip_metadata = false;
+        for format in [ImgFormat::Jpeg, ImgFormat::Png] {
+            let kept = encode(img.clone(), format, &set, &meta).unwrap();
+            assert_eq!(read(&kept).1.icc, Some(icc.clone()));
+        }
+    }
+}
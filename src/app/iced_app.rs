@@ -3,7 +3,12 @@ use std::{path::PathBuf, sync::Arc, time::Duration, vec};
 use bytes::Bytes;
 use iced::{
     Element, Event, Subscription, Task, Theme, event, exit,
-    keyboard::{Event::KeyReleased, Key, key::Named},
+    keyboard::{
+        Event::{KeyPressed, KeyReleased},
+        Key, Modifiers,
+        key::Named,
+    },
+    widget::operation::{self, AbsoluteOffset},
     window::{self, icon},
 };
 use log::{error, info, warn};
@@ -14,21 +19,38 @@ use tokio::{sync::Semaphore, time::sleep};
 use crate::{
     ImgHandle,
     api::{
+        cache, net,
         queue::{
             Queue, QueueMessage,
             Source::{self, YoutubeAlbum},
             TagsInput,
         },
+        registry::{SourceKind, SourceRegistry},
         shared,
     },
     app::{
+        album::{self, Album},
+        auto::{self, AutoSettings},
+        config::Config,
+        crop::ImgEdit,
         img::{ImageProgress, ImageSettings, ImgFormat, ImgId, SongImg},
+        keys::{KeyAction, KeyBindings},
         song::{OrigArt, Song, SongHash, SongId, SongState},
+        song_view::{LIST_PADDING, LIST_SPACING},
         styles::*,
-        view::{PreviewState, REGEX_LIM, view},
+        view::{LIST_ID, PreviewState, REGEX_LIM, view},
+        weights::{PickStats, SourceWeights},
+    },
+    parser::{
+        file_parser::{self, ApplySettings, ParseSettings, RegexType, get_tags_data},
+        journal::Journal,
+        provenance, release_tags, report,
     },
-    parser::file_parser::{self, ParseSettings, RegexType, get_tags_data},
 };
+
+/// Config is written once settings stay unchanged this long
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 pub enum Message {
     FileOpen,
@@ -38,38 +60,75 @@ pub enum Message {
     PushSongs(Vec<Song>),
     PathDropped(Vec<FileHandle>),
     DownscaleInput(String),
+    ResizeModePressed,
+    MinSizeInput(String),
+    FilterTypePressed,
     AddRegex,
     RemoveRegex,
     ParseToggle,
     SquareToggle,
-    JpgToggle,
+    SquareFillPressed,
+    TrimToggle,
+    FormatPressed,
+    QualityInput(u8),
+    OptimizePngToggle,
+    StripMetaToggle,
+    MaxKbInput(String),
+    CoverTargetPressed,
+    DryRunToggle,
+    BackupToggle,
+    UndoLast,
+    UndoSession,
+    FolderFileInput(String),
     RecursiveToggle,
+    ProblemsToggle,
+    MinArtInput(String),
+    SourceToggle(SourceKind),
+    ApiKeyInput(SourceKind, String),
+    WeightInput(Source, String),
+    LearnToggle,
+    ClearCache,
     FilterPressed(usize),
     SeparatorInput(usize, String),
     TitleInput(SongId, String),
     AlbumInput(SongId, String),
     ArtistInput(SongId, String),
     ConfirmSongIfNot(SongId),
+    DetachFromAlbum(SongId),
     AutoModToggle(bool),
+    ThresholdInput(String),
+    AutoLimitInput(String),
+    ReviewOnlyToggle,
     DiscardSong(SongId),
     GoBackDiscard(SongId),
     GoBack(SongId),
     ApplySelectedPressed(SongId),
     ApplySelected(SongId),
-    DecodeAccept(Bytes, ImgFormat, SongId),
+    DecodeAccept(Bytes, String, ImgFormat, SongId),
     AcceptFailed(SongId),
 
     // INFO: potentially return imgs into mtx
     FromQueue(SongId, SongHash, QueueMessage),
     GotArt(SongId, SongHash, SongImg),
+    ArtFailed(SongId, SongHash),
     AutoModTrigger,
     ProcessedArt(SongId, SongHash, SongImg),
     Scroll(f32),
+    KeyInput(Key, Modifiers),
     ImgSelect(SongId, ImgId),
+    TagFromRelease(SongId, ImgId),
     ImgPreviewOpen(SongId, ImgId),
     ImgPreview(SongId, ImgId),
     ImgPreviewSet(PreviewState),
-    DecodePreview(Bytes, ImgFormat, SongId, ImgId),
+    EditPreview(bool),
+    EditRotate,
+    EditFlip(bool),
+    EditReset,
+    CropDrag(bool),
+    CropMove(f32, f32),
+    CropNudge(f32, f32),
+    CropResize(f32),
+    DecodePreview(Bytes, String, ImgFormat, SongId, ImgId),
     ImgMenuToggle(bool, SongId, ImgId),
     TagToggle(SongId, usize),
     LoadOrigImg(SongId),
@@ -86,24 +145,87 @@ pub enum Message {
     ExitAbout,
     Start,
     AfterStart,
+    SaveConfig(u64),
     Exit,
     Nothing,
 }
+impl Message {
+    /// Settings that are saved into config shortly after the last change
+    fn changes_config(&self) -> bool {
+        use Message::*;
+        matches!(
+            self,
+            DownscaleInput(_)
+                | ResizeModePressed
+                | MinSizeInput(_)
+                | FilterTypePressed
+                | AddRegex
+                | RemoveRegex
+                | ParseToggle
+                | SquareToggle
+                | SquareFillPressed
+                | TrimToggle
+                | FormatPressed
+                | QualityInput(_)
+                | OptimizePngToggle
+                | StripMetaToggle
+                | MaxKbInput(_)
+                | CoverTargetPressed
+                | FolderFileInput(_)
+                | BackupToggle
+                | RecursiveToggle
+                | ProblemsToggle
+                | MinArtInput(_)
+                | SourceToggle(_)
+                | ApiKeyInput(_, _)
+                | WeightInput(_, _)
+                | LearnToggle
+                | FilterPressed(_)
+                | SeparatorInput(_, _)
+                | AutoModToggle(_)
+                | ThresholdInput(_)
+                | AutoLimitInput(_)
+        )
+    }
+}
 
 #[derive(Default)]
 pub struct State {
     pub list_scroll: f32,
+    pub focus: Option<SongId>,
+    pub focus_tag: usize,
+    pub keys: KeyBindings,
     pub songs: Vec<Song>,
+    pub albums: Vec<Album>,
     pub preview_img: PreviewState,
     pub preview_client: Client,
+    pub crop_drag: bool,
+    pub crop_cursor: (f32, f32),
     pub ui_blocked: bool,
     pub ui_loading: bool,
     pub parse_settings: ParseSettings,
     pub _init_size: (f32, f32),
     pub auto_mod: bool,
-    pub auto_mod_current_song: usize,
+    pub auto_mod_next: usize,
+    pub auto_settings: AutoSettings,
+    pub review_only: bool,
     pub img_settings: ImageSettings,
+    pub apply_settings: ApplySettings,
+    pub journal: Journal,
+    pub sources: SourceRegistry,
+    pub weights: SourceWeights,
+    pub stats: PickStats,
     pub copied_message: bool,
+    /// Counts config changes, a save waits until no newer change came
+    pub config_edit: u64,
+    pub config_dirty: bool,
+}
+impl State {
+    /// Song is drawn in the list, the review filter hides confident and untouched songs
+    pub fn is_listed(&self, id: SongId) -> bool {
+        let song = &self.songs[id];
+        song.state != SongState::Hidden && (!self.review_only || song.review.is_some())
+    }
 }
 pub fn song_is_invalid(st: &State, id: SongId, hash: SongHash) -> bool {
     if id >= st.songs.len() || st.songs[id].hash != hash {
@@ -130,19 +252,218 @@ impl CoverUI {
                 window::set_icon(id, icon)
             })
             .chain(Task::done(Message::Start));
+        let config = Config::load();
         (
             Self {
                 theme: Some(miasma_theme()),
                 decode_sem: Arc::new(Semaphore::new(1)),
                 state: State {
                     _init_size: init_size,
+                    sources: config.sources(),
+                    parse_settings: config.parse_settings,
+                    img_settings: config.img_settings,
+                    apply_settings: config.apply_settings,
+                    auto_mod: config.auto_mod,
+                    auto_settings: config.auto_settings,
+                    journal: Journal::new(false, config.keep_backup),
+                    weights: config.source_weights,
+                    keys: config.keybindings,
+                    preview_client: net::client(),
+                    stats: PickStats::load(),
                     ..Default::default()
                 },
             },
             t,
         )
     }
+    /// Loaded songs still hold the tags that were undone
+    fn reload_restored(&mut self, restored: &[PathBuf]) {
+        for song in &mut self.state.songs {
+            if song.state == SongState::Hidden || !restored.contains(&song.tag_data.path) {
+                continue;
+            }
+            if let Err(e) = file_parser::reload_file(song) {
+                error!("{} was not reloaded: {e}", song.tag_data.path.display());
+            }
+        }
+    }
+    /// Change the edit of the image open in the crop editor, a new orientation is redrawn
+    fn edit_preview(&mut self, change: impl FnOnce(&mut ImgEdit, (u32, u32))) {
+        let PreviewState::Editing(_, dims, song_id, img_id) = &self.state.preview_img else {
+            return;
+        };
+        let (dims, song_id, img_id) = (*dims, *song_id, *img_id);
+        let img = &mut self.state.songs[song_id].imgs[img_id];
+        let before = img.edit;
+        change(&mut img.edit, dims);
+        if (before.turns, before.flip_h, before.flip_v)
+            != (img.edit.turns, img.edit.flip_h, img.edit.flip_v)
+        {
+            let (handle, dims) = img.edit_preview();
+            self.state.preview_img = PreviewState::Editing(handle, dims, song_id, img_id);
+        }
+    }
+    /// Auto mode judges a song once its sources finished and every image is decoded.
+    /// Confident picks are applied, the rest wait for review
+    fn judge(&mut self, id: SongId) -> Task<Message> {
+        let song = &mut self.state.songs[id];
+        let (num, out_of) = song.sources_finished;
+        if !self.state.auto_mod
+            || song.judged
+            || song.state != SongState::Main
+            || num != out_of
+            || song.decoding > 0
+        {
+            return Task::none();
+        }
+        song.judged = true;
+        // folder images come preselected and are judged like any pick
+        if song.selected_img.is_none() && !song.imgs.is_empty() {
+            song.selected_img = Some(song.img_groups.first_in_first_group());
+        }
+        let confidence = song
+            .selected_img
+            .map_or(0, |img_id| auto::confidence(song, img_id));
+        if confidence >= self.state.auto_settings.threshold {
+            info!("auto applied with confidence {confidence}");
+            return Task::done(Message::ApplySelectedPressed(id));
+        }
+        song.review = Some(confidence);
+        Task::none()
+    }
+    /// Start the undo batch of a song and write its selected tags. Called right before the
+    /// cover is written, songs applied at once would otherwise share a batch
+    fn write_tags(&mut self, song_id: SongId) -> Result<(), anyhow::Error> {
+        let song = &mut self.state.songs[song_id];
+        // tags and cover of the song and its album are undone together
+        self.state.journal.begin();
+        song.selected_tags
+            .apply_selected(&mut song.tag_data, &mut self.state.journal)
+    }
+    /// Focus the next or previous shown song and scroll it to the top of the list
+    fn move_focus(&mut self, forward: bool) -> Task<Message> {
+        let state = &self.state;
+        let shown = |id: &SongId| state.is_listed(*id);
+        let len = state.songs.len();
+        let next = match state.focus {
+            Some(cur) if forward => (cur + 1..len).find(shown),
+            Some(cur) => (0..cur.min(len)).rev().find(shown),
+            None => (0..len).find(shown),
+        };
+        let Some(next) = next else {
+            return Task::none();
+        };
+        let y: f32 = (0..next)
+            .filter(shown)
+            .map(|id| state.songs[id].state.state_to_h())
+            .filter(|h| *h > 0.0)
+            .map(|h| h + LIST_SPACING)
+            .sum();
+        self.state.focus = Some(next);
+        self.state.focus_tag = 0;
+        operation::scroll_to(
+            LIST_ID,
+            AbsoluteOffset {
+                x: None,
+                y: Some(LIST_PADDING + y),
+            },
+        )
+    }
+    fn key_action(&mut self, action: KeyAction) -> Task<Message> {
+        use KeyAction::*;
+        use Message::*;
+        if !matches!(self.state.preview_img, PreviewState::Closed) {
+            return match action {
+                Preview => Task::done(ImgPreviewSet(PreviewState::Closed)),
+                _ => Task::none(),
+            };
+        }
+        match action {
+            NextSong => return self.move_focus(true),
+            PrevSong => return self.move_focus(false),
+            Undo => return Task::done(UndoLast),
+            _ => (),
+        }
+        let Some(id) = self
+            .state
+            .focus
+            .filter(|id| *id < self.state.songs.len() && self.state.is_listed(*id))
+        else {
+            return self.move_focus(true);
+        };
+        let song = &self.state.songs[id];
+        match action {
+            ImgLeft | ImgRight if song.state == SongState::Main => {
+                let flat = song.img_groups.flat();
+                if flat.is_empty() {
+                    return Task::none();
+                }
+                let pos = song
+                    .selected_img
+                    .and_then(|s| flat.iter().position(|i| *i == s));
+                let pos = match (pos, action) {
+                    (None, _) => 0,
+                    (Some(pos), ImgLeft) => pos.saturating_sub(1),
+                    (Some(pos), _) => (pos + 1).min(flat.len() - 1),
+                };
+                Task::done(ImgSelect(id, flat[pos]))
+            }
+            Apply if song.state == SongState::Confirm => Task::done(ConfirmSongIfNot(id)),
+            Apply if song.state == SongState::Main => {
+                Task::done(ApplySelectedPressed(id)).chain(self.move_focus(true))
+            }
+            Skip => Task::done(DiscardSong(id)).chain(self.move_focus(true)),
+            Preview => match song.selected_img {
+                Some(img_id) => Task::done(ImgPreviewOpen(id, img_id)),
+                None => Task::none(),
+            },
+            NextTag if !song.new_tags.sorted.is_empty() => {
+                self.state.focus_tag = (self.state.focus_tag + 1) % song.new_tags.sorted.len();
+                Task::none()
+            }
+            ToggleTag if self.state.focus_tag < song.new_tags.sorted.len() => {
+                Task::done(TagToggle(id, self.state.focus_tag))
+            }
+            _ => Task::none(),
+        }
+    }
+    fn config(&self) -> Config {
+        Config {
+            parse_settings: self.state.parse_settings.clone(),
+            img_settings: self.state.img_settings,
+            apply_settings: self.state.apply_settings.clone(),
+            auto_mod: self.state.auto_mod,
+            auto_settings: self.state.auto_settings,
+            keep_backup: self.state.journal.keep_backup,
+            source_weights: self.state.weights.clone(),
+            keybindings: self.state.keys.clone(),
+            disabled_sources: self.state.sources.disabled(),
+            api_keys: self.state.sources.keys.clone(),
+            ..Default::default()
+        }
+    }
     pub fn update(&mut self, message: Message) -> Task<Message> {
+        let save = message.changes_config();
+        let task = self.handle(message);
+        if !save {
+            return task;
+        }
+        // inputs and sliders change config on every keystroke or step
+        self.state.config_edit += 1;
+        self.state.config_dirty = true;
+        let edit = self.state.config_edit;
+        Task::batch([
+            task,
+            Task::perform(sleep(SAVE_DELAY), move |_| Message::SaveConfig(edit)),
+        ])
+    }
+    fn save_config(&mut self) {
+        if self.state.config_dirty {
+            self.config().save();
+            self.state.config_dirty = false;
+        }
+    }
+    fn handle(&mut self, message: Message) -> Task<Message> {
         use Message::*;
 
         match message {
@@ -171,27 +492,43 @@ impl CoverUI {
                     ConfirmSongIfNot(0)
                 }));
             }
+            SaveConfig(edit) => {
+                if edit == self.state.config_edit {
+                    self.save_config();
+                }
+            }
             Exit => {
+                self.save_config();
                 return exit();
             }
             Nothing => {}
             GotPath(vec) => {
                 self.state.ui_loading = false;
 
-                return Task::perform(
-                    get_tags_data(vec, self.state.parse_settings.clone()),
-                    |res| {
-                        if let Err(e) = res {
-                            warn!("{e}");
-                            return Nothing;
-                        }
-                        PushSongs(res.unwrap())
-                    },
-                );
+                let set = self.state.parse_settings.clone();
+                let (only_problems, min_size) = (set.only_problems, set.min_art_size);
+                return Task::perform(get_tags_data(vec, set), move |res| {
+                    if let Err(e) = res {
+                        warn!("{e}");
+                        return Nothing;
+                    }
+                    let songs = res.unwrap();
+                    if only_problems {
+                        let total = songs.len();
+                        let songs = report::problematic(songs, min_size);
+                        info!("{} of {total} songs have art problems", songs.len());
+                        return PushSongs(songs);
+                    }
+                    PushSongs(songs)
+                });
             }
             PushSongs(songs) => {
+                let from = self.state.songs.len();
                 self.state.songs.extend(songs);
+                album::group_songs(&mut self.state.songs, from, &mut self.state.albums);
                 info!("{} songs now", self.state.songs.len());
+                // auto mode is restored from config before any song is loaded
+                return Task::done(AutoModTrigger);
             }
             FileOpen => {
                 self.state.ui_blocked = true;
@@ -225,6 +562,21 @@ impl CoverUI {
                 self.state.songs[song_id].selected_img = Some(img_id);
                 return Task::done(ImgMenuToggle(false, song_id, img_id));
             }
+            TagFromRelease(song_id, img_id) => {
+                let song = &mut self.state.songs[song_id];
+                let Some(release) = song.imgs[img_id].release.clone() else {
+                    return Task::none();
+                };
+                self.state.journal.begin();
+                if let Err(e) = release_tags::write_release(
+                    &mut song.tag_data,
+                    &release,
+                    &mut self.state.journal,
+                ) {
+                    error!("{}", e);
+                }
+                return Task::done(ImgMenuToggle(false, song_id, img_id));
+            }
             ImgMenuToggle(enter, song_id, img_id) => {
                 if enter {
                     let now = self.state.songs[song_id].menu_img;
@@ -241,6 +593,13 @@ impl CoverUI {
             SquareToggle => {
                 self.state.img_settings.square = !self.state.img_settings.square;
             }
+            SquareFillPressed => {
+                let set = &mut self.state.img_settings;
+                set.square_fill = set.square_fill.next();
+            }
+            TrimToggle => {
+                self.state.img_settings.trim_borders = !self.state.img_settings.trim_borders;
+            }
             FilterPressed(i) => {
                 self.state.parse_settings.reg_keys[i] =
                     self.state.parse_settings.reg_keys[i].next();
@@ -256,10 +615,10 @@ impl CoverUI {
                     let format = ImgFormat::from_url(urls.first().unwrap());
                     let urls = urls.to_vec();
                     let (t, h) = Task::perform(
-                        async move { shared::get_img(&client, urls).await },
+                        async move { shared::get_img_from(&client, urls).await },
                         move |res| {
-                            if let Ok(bytes) = res {
-                                DecodePreview(bytes, format, song_i, img_id)
+                            if let Ok((bytes, url)) = res {
+                                DecodePreview(bytes, url, format, song_i, img_id)
                             } else {
                                 ImgPreviewSet(PreviewState::Error)
                             }
@@ -273,9 +632,10 @@ impl CoverUI {
                     return Task::done(ImgPreview(song_i, img_id));
                 }
             }
-            DecodePreview(bytes, format, song_id, img_id) => {
+            DecodePreview(bytes, url, format, song_id, img_id) => {
                 self.state.preview_img = PreviewState::Loading;
-                let res = self.state.songs[song_id].imgs[img_id].preview_to_decoded(bytes, format);
+                let res =
+                    self.state.songs[song_id].imgs[img_id].preview_to_decoded(bytes, format, url);
 
                 if let Err(e) = res {
                     error!(
@@ -284,6 +644,9 @@ impl CoverUI {
                     );
                     return Task::none();
                 }
+                // the real size may rank it differently than the probe did
+                let song = &mut self.state.songs[song_id];
+                song.img_groups.resort(&song.imgs);
                 return Task::done(ImgPreview(song_id, img_id));
             }
             ImgPreview(song_id, img_id) => {
@@ -298,15 +661,40 @@ impl CoverUI {
                     h.abort()
                 }
                 self.state.preview_img = state;
+                self.state.crop_drag = false;
             }
+            EditPreview(open) => match &self.state.preview_img {
+                PreviewState::Display(_, song_id, img_id) if open => {
+                    let (song_id, img_id) = (*song_id, *img_id);
+                    let (handle, dims) = self.state.songs[song_id].imgs[img_id].edit_preview();
+                    self.state.preview_img = PreviewState::Editing(handle, dims, song_id, img_id);
+                }
+                PreviewState::Editing(_, _, song_id, img_id) if !open => {
+                    self.state.crop_drag = false;
+                    return Task::done(ImgPreview(*song_id, *img_id));
+                }
+                _ => (),
+            },
+            EditRotate => self.edit_preview(|edit, _| edit.rotate()),
+            EditFlip(horizontal) => self.edit_preview(|edit, _| edit.flip(horizontal)),
+            EditReset => self.edit_preview(|edit, _| *edit = ImgEdit::default()),
+            CropDrag(drag) => {
+                self.state.crop_drag = drag;
+                let (x, y) = self.state.crop_cursor;
+                if drag {
+                    self.edit_preview(|edit, dims| edit.move_crop(x, y, dims));
+                }
+            }
+            CropMove(x, y) => {
+                self.state.crop_cursor = (x, y);
+                if self.state.crop_drag {
+                    self.edit_preview(|edit, dims| edit.move_crop(x, y, dims));
+                }
+            }
+            CropNudge(dx, dy) => self.edit_preview(|edit, dims| edit.nudge_crop(dx, dy, dims)),
+            CropResize(factor) => self.edit_preview(|edit, dims| edit.resize_crop(factor, dims)),
             ApplySelectedPressed(song_id) => {
                 let song = &mut self.state.songs[song_id];
-
-                if let Err(e) = song.selected_tags.apply_selected(&mut song.tag_data) {
-                    error!("{}", e);
-                    return Task::done(DiscardSong(song_id));
-                }
-
                 if let Some(img_id) = song.selected_img {
                     let img = &mut song.imgs[img_id];
                     let client = self.state.preview_client.clone();
@@ -315,12 +703,11 @@ impl CoverUI {
                         let format = ImgFormat::from_url(urls.first().unwrap());
                         let urls = urls.to_vec();
                         Task::perform(
-                            async move { shared::get_img(&client, urls).await },
-                            move |res| {
-                                if let Ok(bytes) = res {
-                                    DecodeAccept(bytes, format, song_id)
-                                } else {
-                                    error!("{}", res.unwrap_err());
+                            async move { shared::get_img_from(&client, urls).await },
+                            move |res| match res {
+                                Ok((bytes, url)) => DecodeAccept(bytes, url, format, song_id),
+                                Err(e) => {
+                                    error!("{}", e);
                                     AcceptFailed(song_id)
                                 }
                             },
@@ -331,6 +718,10 @@ impl CoverUI {
                     };
                     return task.chain(Task::done(AutoModTrigger));
                 } else {
+                    if let Err(e) = self.write_tags(song_id) {
+                        error!("{}", e);
+                        return Task::done(DiscardSong(song_id));
+                    }
                     return Task::done(GoBack(song_id));
                 }
             }
@@ -338,13 +729,13 @@ impl CoverUI {
             AcceptFailed(song_id) => {
                 self.state.songs[song_id].state = SongState::Main;
             }
-            DecodeAccept(bytes, format, song_id) => {
+            DecodeAccept(bytes, url, format, song_id) => {
                 let song = &mut self.state.songs[song_id];
 
                 if let Some(img_id) = song.selected_img {
                     self.state.songs[song_id].state = SongState::MainLoading;
-                    let res =
-                        self.state.songs[song_id].imgs[img_id].preview_to_decoded(bytes, format);
+                    let res = self.state.songs[song_id].imgs[img_id]
+                        .preview_to_decoded(bytes, format, url);
 
                     if let Err(e) = res {
                         error!(
@@ -359,18 +750,74 @@ impl CoverUI {
                 }
             }
             ApplySelected(song_id) => {
-                if let Err(e) = file_parser::apply_selected(self, song_id) {
+                if let Err(e) = self.write_tags(song_id) {
                     error!("{}", e);
                     return Task::done(DiscardSong(song_id));
                 }
-                return Task::done(GoBack(song_id));
+                let cover = match file_parser::apply_selected(
+                    &mut self.state.songs[song_id],
+                    &self.state.img_settings,
+                    &self.state.apply_settings,
+                    &mut self.state.journal,
+                ) {
+                    Ok(cover) => cover,
+                    Err(e) => {
+                        error!("{}", e);
+                        return Task::done(DiscardSong(song_id));
+                    }
+                };
+                // picks of auto mode are not the user's
+                let song = &self.state.songs[song_id];
+                if self.state.weights.learn
+                    && !self.state.auto_mod
+                    && !self.state.journal.dry_run
+                    && let Some(img_id) = song.selected_img
+                {
+                    let src = song.imgs[img_id].src;
+                    self.state.stats.record(&song.tag_data.library, src);
+                    self.state.stats.save();
+                }
+                let mut written = Vec::new();
+                if let Some(mut cover) = cover
+                    && let Some(album_id) = self.state.songs[song_id].album
+                {
+                    for member in self.state.albums[album_id].followers() {
+                        let song = &mut self.state.songs[*member];
+                        match file_parser::write_cover(
+                            song,
+                            &mut cover,
+                            &self.state.apply_settings,
+                            &mut self.state.journal,
+                        ) {
+                            Ok(()) => written.push(*member),
+                            Err(e) => error!("{}: {}", song.tag_data.path.display(), e),
+                        }
+                    }
+                }
+                // followers are done with the album, a dry run leaves them to be applied
+                if self.state.journal.dry_run {
+                    written.clear();
+                }
+                let discard = written.into_iter().map(|id| Task::done(DiscardSong(id)));
+                return Task::done(GoBack(song_id)).chain(Task::batch(discard));
+            }
+            DetachFromAlbum(id) => {
+                album::detach(&mut self.state.songs, &mut self.state.albums, id);
             }
             ConfirmSongIfNot(id) => {
+                // tracks of an album are searched once, by the leader
+                if id < self.state.songs.len()
+                    && let Some(leader) = album::follows(&self.state.songs, &self.state.albums, id)
+                {
+                    return Task::done(ConfirmSongIfNot(leader));
+                }
                 if self.state.songs.len() > id && self.state.songs[id].state == SongState::Confirm {
                     let song = &mut self.state.songs[id];
                     let info = TagsInput::from_data(id, song.hash, &song.tag_data);
+                    let sources = self.state.sources.clone();
                     song.state = SongState::Main;
-                    let (q, handle) = Queue::init(info);
+                    song.sources_finished = (0, sources.enabled().len() as i32);
+                    let (q, handle) = Queue::init(info, sources);
                     song.queue_handle = Some(handle);
 
                     song.new_tags
@@ -388,6 +835,7 @@ impl CoverUI {
                 return Task::done(AutoModTrigger);
             }
             DiscardSong(id) => {
+                album::detach(&mut self.state.songs, &mut self.state.albums, id);
                 self.state.songs[id].state = SongState::Hidden;
                 //Lazy GC
                 while !self.state.songs.is_empty()
@@ -405,6 +853,17 @@ impl CoverUI {
                     self.state.img_settings.downscale = 0;
                 }
             }
+            ResizeModePressed => {
+                let set = &mut self.state.img_settings;
+                set.resize_mode = set.resize_mode.next();
+            }
+            MinSizeInput(num) => {
+                self.state.img_settings.min_size = num.parse::<u32>().unwrap_or(0).min(10000);
+            }
+            FilterTypePressed => {
+                let set = &mut self.state.img_settings;
+                set.filter = set.filter.next();
+            }
             AddRegex => {
                 let st = &mut self.state.parse_settings;
                 if st.reg_keys.len() < REGEX_LIM {
@@ -422,6 +881,38 @@ impl CoverUI {
             RecursiveToggle => {
                 self.state.parse_settings.recursive = !self.state.parse_settings.recursive;
             }
+            ProblemsToggle => {
+                self.state.parse_settings.only_problems = !self.state.parse_settings.only_problems;
+            }
+            MinArtInput(num) => {
+                let res = str::parse::<u32>(&num);
+                if let Ok(num) = res {
+                    self.state.parse_settings.min_art_size = u32::min(10000, num);
+                } else {
+                    self.state.parse_settings.min_art_size = 0;
+                }
+            }
+            SourceToggle(kind) => {
+                self.state.sources.toggle(kind);
+            }
+            ApiKeyInput(kind, key) => {
+                self.state.sources.keys.set(kind, key);
+            }
+            WeightInput(src, num) => {
+                let weight = str::parse::<i32>(&num).unwrap_or(0);
+                self.state.weights.set(src, weight.clamp(-999, 999));
+            }
+            LearnToggle => {
+                self.state.weights.learn = !self.state.weights.learn;
+            }
+            ClearCache => {
+                return Task::perform(cache::clear(), |res| {
+                    if let Err(e) = res {
+                        error!("cache was not cleared: {e}");
+                    }
+                    Nothing
+                });
+            }
             ParseToggle => {
                 self.state.parse_settings.parse_file_name =
                     !self.state.parse_settings.parse_file_name;
@@ -436,8 +927,42 @@ impl CoverUI {
                 self.state.songs[id].tag_data.artist = if s.is_empty() { None } else { Some(s) }
             }
 
-            JpgToggle => {
-                self.state.img_settings.jpg = !self.state.img_settings.jpg;
+            FormatPressed => {
+                let set = &mut self.state.img_settings;
+                set.format = set.format.next();
+            }
+            QualityInput(quality) => self.state.img_settings.jpeg_quality = quality,
+            OptimizePngToggle => {
+                let set = &mut self.state.img_settings;
+                set.optimize_png = !set.optimize_png;
+            }
+            StripMetaToggle => {
+                let set = &mut self.state.img_settings;
+                set.strip_metadata = !set.strip_metadata;
+            }
+            MaxKbInput(num) => {
+                self.state.img_settings.max_kb = num.parse::<u32>().unwrap_or(0).min(100_000);
+            }
+            CoverTargetPressed => {
+                let set = &mut self.state.apply_settings;
+                set.target = set.target.next();
+            }
+            FolderFileInput(name) => {
+                self.state.apply_settings.folder_file = name;
+            }
+            DryRunToggle => {
+                self.state.journal.dry_run = !self.state.journal.dry_run;
+            }
+            BackupToggle => {
+                self.state.journal.keep_backup = !self.state.journal.keep_backup;
+            }
+            UndoLast => {
+                let restored = self.state.journal.undo_last();
+                self.reload_restored(&restored);
+            }
+            UndoSession => {
+                let restored = self.state.journal.undo_session();
+                self.reload_restored(&restored);
             }
 
             FromQueue(id, hash, mes) => {
@@ -447,29 +972,26 @@ impl CoverUI {
                 }
                 use QueueMessage::*;
                 match mes {
-                    GotArt(output) => {
-                        return Task::done(Message::GotArt(id, hash, output));
-                    }
+                    // counted right away, the queue may report finishing next
+                    GotArt(output) => return self.handle(Message::GotArt(id, hash, output)),
+                    Resolution(url, res) => self.state.songs[id].set_resolution(url, res),
                     SetSources(num, out_of) => {
                         self.state.songs[id].sources_finished = (num, out_of);
                         if self.state.auto_mod && num == out_of {
-                            let mut task = Task::none();
-                            if self.state.songs[id].state == SongState::Main
-                                && !self.state.songs[id].imgs.is_empty()
-                                && self.state.songs[id].selected_img.is_none()
-                            {
-                                task = task.chain(Task::done(SelectFirst(id)));
-                            }
-                            return task.chain(Task::done(AutoModTrigger));
+                            return Task::batch([self.judge(id), Task::done(AutoModTrigger)]);
                         }
                     }
                     SourceFinished => {
-                        let now = self.state.songs[id].sources_finished.0;
-                        self.state.songs[id].sources_finished = (now + 1, Queue::TOTAL_SOURCES)
+                        let (now, out_of) = self.state.songs[id].sources_finished;
+                        self.state.songs[id].sources_finished = (now + 1, out_of)
                     }
                 }
             }
             GotArt(id, hash, img) => {
+                if song_is_invalid(&self.state, id, hash) {
+                    return Task::none();
+                }
+                self.state.songs[id].decoding += 1;
                 return Task::perform(
                     SongImg::decode_and_sample(img, self.decode_sem.clone()),
                     move |res| {
@@ -477,51 +999,90 @@ impl CoverUI {
                             ProcessedArt(id, hash, ok)
                         } else {
                             error!("img was not decoded and sapmled: {},", res.unwrap_err());
-                            Nothing
+                            ArtFailed(id, hash)
                         }
                     },
                 );
             }
+            ArtFailed(id, hash) => {
+                if !song_is_invalid(&self.state, id, hash) {
+                    let song = &mut self.state.songs[id];
+                    song.decoding = song.decoding.saturating_sub(1);
+                    return self.judge(id);
+                }
+            }
             AutoModTrigger => {
-                if self.state.auto_mod {
-                    let cur = self.state.auto_mod_current_song;
-                    // current song in process right now
-                    if cur < self.state.songs.len() {
-                        let song = &mut self.state.songs[cur];
-                        let (a, b) = song.sources_finished;
-                        if song.state == SongState::Main && a != b {
-                            return Task::none();
-                        }
-                    }
-                    for i in (cur + 1)..self.state.songs.len() {
-                        if self.state.songs[i].state != SongState::Confirm {
-                            continue;
-                        }
-                        self.state.auto_mod_current_song = i;
-                        return Task::done(ConfirmSongIfNot(i));
+                if !self.state.auto_mod {
+                    return Task::none();
+                }
+                let searching = self
+                    .state
+                    .songs
+                    .iter()
+                    .filter(|s| {
+                        s.state == SongState::Main && s.sources_finished.0 != s.sources_finished.1
+                    })
+                    .count();
+                let mut free = self
+                    .state
+                    .auto_settings
+                    .limit
+                    .max(1)
+                    .saturating_sub(searching);
+                let mut tasks = Vec::new();
+                while free > 0 && self.state.auto_mod_next < self.state.songs.len() {
+                    let i = self.state.auto_mod_next;
+                    self.state.auto_mod_next += 1;
+                    if self.state.songs[i].state != SongState::Confirm
+                        || album::follows(&self.state.songs, &self.state.albums, i).is_some()
+                    {
+                        continue;
                     }
+                    // started right away so the next trigger counts it
+                    tasks.push(self.handle(ConfirmSongIfNot(i)));
+                    free -= 1;
                 }
+                return Task::batch(tasks);
             }
-            ProcessedArt(id, hash, output) => {
+            ProcessedArt(id, hash, mut output) => {
                 let mut task = Task::none();
                 if song_is_invalid(&self.state, id, hash) {
                     return task;
                 }
                 let song = &mut self.state.songs[id];
+                song.decoding = song.decoding.saturating_sub(1);
+                output.weight = self.state.weights.weight(
+                    &self.state.stats,
+                    &song.tag_data.library,
+                    output.src,
+                );
                 if output.src == Source::LocalFile {
                     task = Task::done(SelectFirst(id));
                 }
+                output.take_probed(&mut song.probed);
                 let res = output.push_and_group(&mut song.img_groups, &mut song.imgs);
 
                 let _ = res.inspect_err(|e| warn!("img was not added: {e}"));
-                return task;
+                return task.chain(self.judge(id));
+            }
+            KeyInput(key, modifiers) => {
+                if let Some(action) = self.state.keys.action(&key, modifiers) {
+                    return self.key_action(action);
+                }
             }
+            ThresholdInput(num) => {
+                self.state.auto_settings.threshold = num.parse::<u32>().unwrap_or(0).min(100);
+            }
+            AutoLimitInput(num) => {
+                self.state.auto_settings.limit = num.parse::<usize>().unwrap_or(1).clamp(1, 16);
+            }
+            ReviewOnlyToggle => self.state.review_only = !self.state.review_only,
             Scroll(scroll_uv) => {
                 self.state.list_scroll = scroll_uv;
             }
             AutoModToggle(on) => {
                 self.state.auto_mod = on;
-                self.state.auto_mod_current_song = 0;
+                self.state.auto_mod_next = 0;
                 return Task::done(AutoModTrigger);
             }
 
@@ -581,25 +1142,36 @@ impl CoverUI {
                 return Task::perform(files, move |d| SaveImgLocallyEnd(d, song_id, img_id));
             }
             SaveImgLocallyEnd(data, song_id, img_id) => {
-                if let Some(file_handle) = data {
-                    self.state.songs[song_id].imgs[img_id]
+                if let Some(file_handle) = data
+                    && let Err(e) = self.state.songs[song_id].imgs[img_id]
                         .decoded()
                         .save(file_handle.path())
-                        .unwrap();
+                {
+                    error!(
+                        "image was not saved to {}: {e}",
+                        file_handle.path().display()
+                    );
                 }
             }
             RemoveImageFromFile(song_id) => {
-                let song = &mut self.state.songs[song_id];
-                song.tag_data.file.remove_album_cover();
-                let res = song
-                    .tag_data
-                    .file
-                    .write_to_path(song.tag_data.path.to_str().unwrap());
+                let tag_data = &mut self.state.songs[song_id].tag_data;
+                tag_data.file.remove_album_cover();
+                self.state.journal.begin();
+                // the provenance tag would describe a cover that is gone
+                let res = self.state.journal.write_tags_with(
+                    &tag_data.path,
+                    &mut tag_data.file,
+                    "remove cover",
+                    |temp| provenance::write(temp, None),
+                );
                 if let Err(e) = res {
                     error!("{}", e);
                     return Task::none();
                 }
-                song.original_art = None;
+                if !self.state.journal.dry_run {
+                    tag_data.provenance = None;
+                    self.state.songs[song_id].original_art = None;
+                }
             }
             OrigImageHover(hovered, song_id) => {
                 self.state.songs[song_id].original_art_hovered = hovered;
@@ -674,10 +1246,18 @@ impl CoverUI {
         Task::none()
     }
     pub fn subscription(&self) -> Subscription<Message> {
-        event::listen_with(|event, _status, _windows| match event {
+        event::listen_with(|event, status, _windows| match event {
             Event::Window(window::Event::FileDropped(path)) => {
                 Some(Message::PathDropped(vec![path.into()]))
             }
+            // pending config is saved before the window goes
+            Event::Window(window::Event::CloseRequested) => Some(Message::Exit),
+            // typing into inputs is captured
+            Event::Keyboard(KeyPressed { key, modifiers, .. })
+                if status == event::Status::Ignored =>
+            {
+                Some(Message::KeyInput(key, modifiers))
+            }
             #[cfg(debug_assertions)]
             Event::Keyboard(KeyReleased {
                 key: Key::Named(Named::Escape),
@@ -2,35 +2,79 @@ use audiotags::{MimeType, Picture};
 use bytes::Bytes;
 use image::{DynamicImage, ImageBuffer, ImageFormat, Luma};
 use log::{info, warn};
-
-use crate::{ImgHandle, api::queue::Source, app::img_group::ImgGroups};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ImgHandle,
+    api::queue::Source,
+    app::{
+        crop::{self, ImgEdit, SquareFill},
+        encode::{self, ImgMeta, OutputFormat},
+        img_group::ImgGroups,
+        phash,
+        resize::{self, ResizeFilter, ResizeMode},
+    },
+    parser::release_tags::ReleaseInfo,
+};
 
 use std::{io::Cursor, sync::Arc};
 
 use anyhow::{Error, bail};
 use iced::widget::image::Handle;
-use image::{GenericImageView, ImageReader, imageops::FilterType::Triangle};
+use image::{GenericImageView, ImageReader};
 use image_compare::{Algorithm::MSSIMSimple, gray_similarity_structure};
 use tokio::{sync::Semaphore, task::yield_now};
 
-const THRESHOLD: f64 = 0.3;
-const SORT_LIMIT: usize = 15;
 const PREVIEW_DIM: u32 = 200;
+/// Longer side of the image shown in the crop editor
+const EDIT_DIM: u32 = 1000;
 const COMPARE_DIM: u32 = 200;
-
-#[derive(Clone, Copy, Debug)]
+/// Score point per this many px of the shorter side
+const RES_STEP: u32 = 100;
+const RES_SCORE_LIM: u32 = 30;
+/// Score taken from art that is twice as wide as high, more for wider
+const ASPECT_PENALTY: u32 = 10;
+
+/// * `trim_borders`: cut letterbox bars before squaring
+/// * `square_fill`: how `square` makes the image square
+/// * `jpeg_quality`: 1 to 100, the start point when over `max_kb`
+/// * `optimize_png`: slower, smaller png
+/// * `strip_metadata`: drop color profile and exif of the downloaded file
+/// * `max_kb`: largest written cover, 0 for no limit
+/// * `downscale`: largest size of the side picked by `resize_mode`
+/// * `min_size`: smaller art is upscaled to it, 0 keeps it as is
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ImageSettings {
     pub downscale: u32,
+    pub resize_mode: ResizeMode,
+    pub min_size: u32,
+    pub filter: ResizeFilter,
     pub square: bool,
-    pub jpg: bool,
+    pub trim_borders: bool,
+    pub square_fill: SquareFill,
+    pub format: OutputFormat,
+    pub jpeg_quality: u8,
+    pub optimize_png: bool,
+    pub strip_metadata: bool,
+    pub max_kb: u32,
 }
 
 impl Default for ImageSettings {
     fn default() -> Self {
         Self {
             downscale: 1200,
-            jpg: true,
+            resize_mode: ResizeMode::Height,
+            min_size: 0,
+            filter: ResizeFilter::Triangle,
             square: true,
+            trim_borders: true,
+            square_fill: SquareFill::Crop,
+            format: OutputFormat::Jpeg,
+            jpeg_quality: 90,
+            optimize_png: false,
+            strip_metadata: true,
+            max_kb: 0,
         }
     }
 }
@@ -113,6 +157,10 @@ impl ImageProgress {
 pub type ImgId = usize;
 #[derive(Clone, Debug)]
 /// * `orig_format`: format of the full image, preview image format will be guessed
+/// * `release`: structured form of `feedback` for sources that know the release
+/// * `url`: full size image, known once a preview was downloaded
+/// * `weight`: of the source, set from settings and picks before the image is grouped
+/// * `hash`: `phash::dhash` of the sample crop, groups images
 pub struct SongImg {
     pub orig_format: ImgFormat,
     pub src: Source,
@@ -120,7 +168,13 @@ pub struct SongImg {
     pub orig_res: Option<(u32, u32)>,
     pub preview: Option<ImgHandle>,
     pub sample: Option<SortSample>,
+    pub hash: Option<u64>,
     pub feedback: String,
+    pub release: Option<ReleaseInfo>,
+    pub url: Option<String>,
+    pub weight: i32,
+    pub edit: ImgEdit,
+    pub meta: ImgMeta,
 }
 impl SongImg {
     pub fn new(format: ImgFormat, image: ImageProgress, src: Source, feedback: String) -> Self {
@@ -131,9 +185,47 @@ impl SongImg {
             orig_res: None,
             preview: None,
             sample: None,
+            hash: None,
             feedback,
+            release: None,
+            url: None,
+            weight: src.get_weight(),
+            edit: ImgEdit::default(),
+            meta: ImgMeta::default(),
+        }
+    }
+    pub fn with_release(mut self, release: ReleaseInfo) -> Self {
+        self.release = Some(release);
+        self
+    }
+    /// Url the preview was sent with, until the full image is downloaded
+    pub fn preview_url(&self) -> Option<&str> {
+        match &self.image {
+            ImageProgress::RawPreview(urls, _) | ImageProgress::Preview(urls) => {
+                urls.first().map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+    /// Take the resolution probed for this preview out of `probed`
+    pub fn take_probed(&mut self, probed: &mut Vec<(String, (u32, u32))>) {
+        if let Some(i) = probed
+            .iter()
+            .position(|(url, _)| Some(url.as_str()) == self.preview_url())
+        {
+            self.orig_res = Some(probed.swap_remove(i).1);
         }
     }
+    /// Weight, plus resolution and minus distance from a square when the size is known
+    pub fn score(&self) -> i32 {
+        let mut score = self.weight;
+        if let Some((w, h)) = self.orig_res {
+            let (short, long) = (w.min(h), w.max(h).max(1));
+            score += (short / RES_STEP).min(RES_SCORE_LIM) as i32;
+            score -= ((long - short) * ASPECT_PENALTY * 2 / long) as i32;
+        }
+        score
+    }
     pub fn decoded(&self) -> DynamicImage {
         match &self.image {
             ImageProgress::Decoded(d) => d.clone(),
@@ -150,9 +242,7 @@ impl SongImg {
             _ => panic!("not raw"),
         };
 
-        let res = ImageReader::new(Cursor::new(bytes))
-            .with_guessed_format()?
-            .decode();
+        let res = encode::decode(ImageReader::new(Cursor::new(bytes)).with_guessed_format()?);
         if let Err(e) = res {
             bail!(
                 "preview img was not decoded: {e}, format: {:?}, urls: {:?}, feedback: {} ",
@@ -162,12 +252,13 @@ impl SongImg {
             );
         }
 
-        let decoded = res.unwrap();
+        let (decoded, meta) = res.unwrap();
         let (w, h) = decoded.dimensions();
         if let Some(urls) = urls {
             self.image = ImageProgress::Preview(urls.to_vec());
         } else {
             self.orig_res = Some((w, h));
+            self.meta = meta;
             self.image = ImageProgress::Decoded(decoded.clone());
         }
 
@@ -178,7 +269,8 @@ impl SongImg {
         let (w, h) = dyn_img.dimensions();
         let dyn_clone = dyn_img.clone();
 
-        let dyn_img = dyn_img.crop_imm(w / 2 - h / 2, 0, h, h);
+        let dyn_img = crop::center_square(&crop::trim_borders(&dyn_img));
+        let hash = phash::dhash(&dyn_img);
 
         let dyn_img = dyn_img.thumbnail_exact(COMPARE_DIM, COMPARE_DIM);
         yield_now().await;
@@ -194,6 +286,7 @@ impl SongImg {
             }
             _ => Some(dyn_img.clone().into_luma8()),
         };
+        self.hash = self.sample.as_ref().map(|_| hash);
 
         drop(permit);
         Ok(self)
@@ -216,50 +309,78 @@ impl SongImg {
         let bytes = Bytes::from_owner(rgb.into_raw());
         Some(Handle::from_rgba(w, h, bytes))
     }
+    /// Join the group with the closest hash, MSSIM of the samples decides between equally close groups
     pub fn push_and_group(
-        mut self,
+        self,
         groups: &mut ImgGroups,
         all: &mut Vec<SongImg>,
     ) -> Result<(), Error> {
-        if self.sample.is_none() {
-            groups.add_new(all.len(), self.src.get_weight());
+        let (Some(hash), Some(sample)) = (self.hash, &self.sample) else {
+            groups.add_new(all.len(), self.score());
             all.push(self);
             return Ok(());
-        }
-        let b = self.sample.unwrap();
+        };
 
+        let mut closest = Vec::new();
+        let mut min_distance = phash::HASH_THRESHOLD;
         for group_i in 0..groups.len() {
-            if group_i > SORT_LIMIT {
-                break;
+            let Some(group_hash) = all[groups.first_in_group(group_i)].hash else {
+                continue;
+            };
+            let distance = phash::distance(hash, group_hash);
+            if distance < min_distance {
+                closest.clear();
+                min_distance = distance;
             }
-            if let Some(a) = all[groups.first_in_group(group_i)].sample.as_ref() {
-                let score = gray_similarity_structure(&MSSIMSimple, a, &b)?.score;
-
-                if score > THRESHOLD {
-                    self.sample = Some(b);
-                    groups.add_to_group(group_i, &self, all.len(), all);
-                    all.push(self);
-                    return Ok(());
-                }
+            if distance == min_distance {
+                closest.push(group_i);
             }
         }
-        groups.add_new(all.len(), self.src.get_weight());
-        self.sample = Some(b);
+        let group_i = match closest.as_slice() {
+            [] => {
+                groups.add_new(all.len(), self.score());
+                all.push(self);
+                return Ok(());
+            }
+            [group_i] => *group_i,
+            _ => {
+                let mut best = (closest[0], f64::MIN);
+                for group_i in closest {
+                    let Some(a) = all[groups.first_in_group(group_i)].sample.as_ref() else {
+                        continue;
+                    };
+                    let score = gray_similarity_structure(&MSSIMSimple, a, sample)?.score;
+                    if score > best.1 {
+                        best = (group_i, score);
+                    }
+                }
+                best.0
+            }
+        };
+        groups.add_to_group(group_i, &self, all.len(), all);
         all.push(self);
         Ok(())
     }
 
-    pub fn preview_to_decoded(&mut self, bytes: Bytes, format: ImgFormat) -> Result<(), Error> {
+    /// * `url`: the one of the preview urls that answered
+    pub fn preview_to_decoded(
+        &mut self,
+        bytes: Bytes,
+        format: ImgFormat,
+        url: String,
+    ) -> Result<(), Error> {
         self.orig_format = format;
+        self.url = Some(url);
 
         let guessed = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
 
         // in case url extension lied
         self.orig_format = ImgFormat::from_imageio(guessed.format().unwrap());
 
-        let preprocessed = guessed.decode()?;
+        let (preprocessed, meta) = encode::decode(guessed)?;
         let (w, h) = preprocessed.dimensions();
         self.orig_res = Some((w, h));
+        self.meta = meta;
 
         info!("img decoded {}", self.feedback);
         self.image = ImageProgress::Decoded(preprocessed);
@@ -273,8 +394,21 @@ impl SongImg {
         let scaled = scaled.to_rgba8();
         ImgHandle::from_rgba(w, h, Bytes::from_owner(scaled.into_raw()))
     }
+    /// Turned and flipped image for the crop editor with the full size it stands for,
     /// self.image has to be decoded
-    pub fn final_img(&mut self, set: &ImageSettings) -> (Bytes, ImgFormat, Handle) {
+    pub fn edit_preview(&self) -> (ImgHandle, (u32, u32)) {
+        let oriented = self.edit.orient(self.decoded());
+        let dims = oriented.dimensions();
+        let shown = oriented.thumbnail(EDIT_DIM, EDIT_DIM);
+        let (w, h) = shown.dimensions();
+        let shown = shown.to_rgba8();
+        (
+            ImgHandle::from_rgba(w, h, Bytes::from_owner(shown.into_raw())),
+            dims,
+        )
+    }
+    /// self.image has to be decoded
+    pub fn final_img(&mut self, set: &ImageSettings) -> Result<(Bytes, ImgFormat, Handle), Error> {
         let scaled = self.apply_settings(set);
 
         let preview = scaled.thumbnail(PREVIEW_DIM * 2, PREVIEW_DIM);
@@ -282,32 +416,51 @@ impl SongImg {
         let preview = preview.to_rgba8();
         let handle = ImgHandle::from_rgba(w, h, Bytes::from_owner(preview.into_raw()));
 
-        let mut new_img = Vec::<u8>::new();
-        let mut buf = Cursor::new(&mut new_img);
-        let format = if set.jpg {
-            ImgFormat::Jpeg
+        let format = set.format.resolve(self.orig_format);
+        let new_img = encode::encode(scaled, format, set, &self.meta)?;
+        Ok((Bytes::from_owner(new_img), format, handle))
+    }
+    fn apply_settings(&mut self, set: &ImageSettings) -> DynamicImage {
+        let raw = self.edit.orient(self.decoded());
+        let cropped = if let Some(cropped) = self.edit.crop(&raw) {
+            cropped
         } else {
-            self.orig_format
+            let raw = if set.trim_borders {
+                crop::trim_borders(&raw)
+            } else {
+                raw
+            };
+            if set.square {
+                crop::to_square(raw, set.square_fill)
+            } else {
+                raw
+            }
         };
-        scaled.write_to(&mut buf, format.imageio()).unwrap();
-        (Bytes::from_owner(new_img), format, handle)
+        resize::resize(cropped, set)
     }
-    fn apply_settings(&mut self, set: &ImageSettings) -> DynamicImage {
-        let raw = self.decoded();
-
-        let (w, h) = raw.dimensions();
-        let cropped = if set.square && w > h {
-            raw.crop_imm(w / 2 - h / 2, 0, h, h)
+    /// Final image would be larger than the art, guessed from the original resolution
+    pub fn upscaled(&self, set: &ImageSettings) -> bool {
+        let Some((w, h)) = self.orig_res else {
+            return false;
+        };
+        let oriented = if self.edit.turns % 2 == 1 {
+            (h, w)
         } else {
-            raw
+            (w, h)
         };
-        let (_, h) = cropped.dimensions();
-
-        if set.downscale < h {
-            cropped.resize(9999, set.downscale, Triangle)
+        let dims = if let Some(crop) = self.edit.crop {
+            let (_, _, side) = crop.pixels(oriented);
+            (side, side)
+        } else if set.square {
+            let side = match set.square_fill {
+                SquareFill::Crop => w.min(h),
+                _ => w.max(h),
+            };
+            (side, side)
         } else {
-            cropped
-        }
+            oriented
+        };
+        resize::upscales(dims, set)
     }
 }
 fn from_mime(mime: MimeType) -> ImageFormat {
@@ -319,3 +472,49 @@ fn from_mime(mime: MimeType) -> ImageFormat {
         MimeType::Jpeg => ImageFormat::Jpeg,
     }
 }
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use bytes::Bytes;
+    use image::{ImageFormat, imageops::FilterType::Triangle};
+    use tokio::{runtime::Runtime, sync::Semaphore};
+
+    use crate::{
+        api::queue::Source::{DeezerAlbum, ItunesAlbum},
+        app::{
+            img::{ImageProgress::Raw, ImgFormat::Png, SongImg},
+            img_group::ImgGroups,
+            phash::tests::cover,
+        },
+    };
+
+    #[test]
+    fn groups_by_hash() {
+        let covers = [
+            cover(0, 600),
+            cover(1, 600),
+            cover(0, 600).resize(250, 250, Triangle),
+            cover(1, 600).brighten(15),
+            cover(2, 600),
+        ];
+        let sem = Arc::new(Semaphore::new(1));
+        let rt = Runtime::new().unwrap();
+        let mut groups = ImgGroups::new();
+        let mut all = Vec::new();
+        for (i, img) in covers.iter().enumerate() {
+            let mut bytes = Vec::new();
+            img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .unwrap();
+            let src = if i < 2 { ItunesAlbum } else { DeezerAlbum };
+            let img = SongImg::new(Png, Raw(Bytes::from(bytes)), src, String::new());
+            let img = rt.block_on(img.decode_and_sample(sem.clone())).unwrap();
+            img.push_and_group(&mut groups, &mut all).unwrap();
+        }
+        assert_eq!(groups.len(), 3);
+        let flat = groups.flat();
+        let first = |id: usize| flat.iter().position(|i| *i == id).unwrap();
+        assert_eq!(first(2), first(0) + 1);
+        assert_eq!(first(3), first(1) + 1);
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::{cmp::Reverse, ops::Range};
 
 use log::info;
 
@@ -27,6 +27,16 @@ impl ImgGroups {
     pub fn len(&self) -> usize {
         self.groups.len()
     }
+    /// Image ids of a group, best first
+    pub fn group(&self, group_id: usize) -> &[usize] {
+        &self.groups[group_id].imgs
+    }
+    /// Group that holds the image
+    pub fn group_of(&self, img_id: usize) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|group| group.imgs.contains(&img_id))
+    }
     pub fn first_in_group(&self, group_id: usize) -> usize {
         self.groups[group_id].imgs[0]
     }
@@ -46,7 +56,7 @@ impl ImgGroups {
         new_img_id: usize,
         imgs: &[SongImg],
     ) {
-        let new_img_weight = new_img.src.get_weight();
+        let new_img_weight = new_img.score();
         let group = &mut self.groups[group_id];
         group.weight += new_img_weight;
 
@@ -54,7 +64,7 @@ impl ImgGroups {
         let group = &mut self.groups[group_id].imgs;
         group.push(new_img_id);
 
-        while new_i > 0 && new_img_weight > imgs[group[new_i - 1]].src.get_weight() {
+        while new_i > 0 && new_img_weight > imgs[group[new_i - 1]].score() {
             group.swap(new_i - 1, new_i);
             new_i -= 1;
         }
@@ -71,6 +81,20 @@ impl ImgGroups {
         self.sort_groups(self.groups.len() - 1);
         self.update_flat();
     }
+    /// Sort again after scores changed, e.g. a resolution became known
+    pub fn resort(&mut self, imgs: &[SongImg]) {
+        for group in &mut self.groups {
+            group.imgs.sort_by_key(|i| Reverse(imgs[*i].score()));
+            group.weight = group.imgs.iter().map(|i| imgs[*i].score()).sum();
+        }
+        self.groups.sort_by_key(|group| Reverse(group.weight));
+        self.flat.clear();
+        self.flat.extend(
+            self.groups
+                .iter()
+                .flat_map(|group| group.imgs.iter().copied()),
+        );
+    }
     fn sort_groups(&mut self, group_id: usize) {
         let mut move_id = group_id;
         while move_id > 0 && self.groups[move_id - 1].weight < self.groups[group_id].weight {
@@ -96,7 +120,7 @@ impl ImgGroups {
 #[cfg(test)]
 mod tests {
     use crate::{
-        api::queue::Source::{BandcampAlbum, LocalFile, YoutubeTitle},
+        api::queue::Source::{BandcampAlbum, BrainzTitle, LocalFile, YoutubeTitle},
         app::{
             img::{ImageProgress::Preview, ImgFormat::Jpeg, SongImg},
             img_group::{self, ImgGroup, ImgGroups},
@@ -152,4 +176,38 @@ mod tests {
         img_groups.add_to_group(1, &img4, imgs.len(), &imgs);
         imgs.push(img4);
     }
+    #[test]
+    fn resolution_beats_weight() {
+        let mut thumb = SongImg::new(Jpeg, Preview(vec![]), BrainzTitle, "".to_string());
+        thumb.orig_res = Some((300, 300));
+        let mut scan = SongImg::new(Jpeg, Preview(vec![]), YoutubeTitle, "".to_string());
+        scan.orig_res = Some((3000, 3000));
+        let mut wide = SongImg::new(Jpeg, Preview(vec![]), YoutubeTitle, "".to_string());
+        wide.orig_res = Some((3000, 1000));
+        assert!(scan.score() > thumb.score());
+        assert!(scan.score() > wide.score());
+
+        let mut img_groups = ImgGroups::new();
+        img_groups.add_new(0, thumb.score());
+        let imgs = vec![thumb];
+        img_groups.add_to_group(0, &scan, 1, &imgs);
+        assert_eq!(img_groups.flat(), &vec![1, 0]);
+    }
+    #[test]
+    fn resorted_on_resolution() {
+        let mut imgs = vec![
+            SongImg::new(Jpeg, Preview(vec![]), BandcampAlbum, "".to_string()),
+            SongImg::new(Jpeg, Preview(vec![]), YoutubeTitle, "".to_string()),
+        ];
+        imgs[1].weight = imgs[0].weight - 1;
+        let mut img_groups = ImgGroups::new();
+        img_groups.add_new(0, imgs[0].score());
+        img_groups.add_new(1, imgs[1].score());
+        assert_eq!(img_groups.flat(), &vec![0, 1]);
+
+        imgs[1].orig_res = Some((3000, 3000));
+        img_groups.resort(&imgs);
+        assert_eq!(img_groups.flat(), &vec![1, 0]);
+        assert_eq!(img_groups.first_in_first_group(), 1);
+    }
 }
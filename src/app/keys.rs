@@ -0,0 +1,123 @@
+//! Keyboard shortcuts to review the song list, kept in the config
+use iced::keyboard::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    NextSong,
+    PrevSong,
+    ImgLeft,
+    ImgRight,
+    Apply,
+    Skip,
+    Preview,
+    NextTag,
+    ToggleTag,
+    Undo,
+}
+
+/// A character or a named key like `ArrowDown`, `Enter` or `Space`,
+/// optionally after `ctrl+`, `shift+`, `alt+` or `logo+`. Empty turns the action off
+/// * `apply`: confirms a song that was not searched yet
+/// * `skip`: hides the song without writing anything
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub next_song: String,
+    pub prev_song: String,
+    pub img_left: String,
+    pub img_right: String,
+    pub apply: String,
+    pub skip: String,
+    pub preview: String,
+    pub next_tag: String,
+    pub toggle_tag: String,
+    pub undo: String,
+}
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            next_song: "ArrowDown".to_string(),
+            prev_song: "ArrowUp".to_string(),
+            img_left: "ArrowLeft".to_string(),
+            img_right: "ArrowRight".to_string(),
+            apply: "Enter".to_string(),
+            skip: "Delete".to_string(),
+            preview: "Space".to_string(),
+            next_tag: "Tab".to_string(),
+            toggle_tag: "t".to_string(),
+            undo: "ctrl+z".to_string(),
+        }
+    }
+}
+impl KeyBindings {
+    pub fn action(&self, key: &Key, modifiers: Modifiers) -> Option<KeyAction> {
+        use KeyAction::*;
+        [
+            (NextSong, &self.next_song),
+            (PrevSong, &self.prev_song),
+            (ImgLeft, &self.img_left),
+            (ImgRight, &self.img_right),
+            (Apply, &self.apply),
+            (Skip, &self.skip),
+            (Preview, &self.preview),
+            (NextTag, &self.next_tag),
+            (ToggleTag, &self.toggle_tag),
+            (Undo, &self.undo),
+        ]
+        .into_iter()
+        .find(|(_, binding)| matches(binding, key, modifiers))
+        .map(|(action, _)| action)
+    }
+}
+/// Characters match in any case, shift has to be written out
+fn matches(binding: &str, key: &Key, modifiers: Modifiers) -> bool {
+    let mut parts: Vec<_> = binding.split('+').map(str::trim).collect();
+    let name = parts.pop().unwrap_or_default();
+    let mut wanted = Modifiers::empty();
+    for part in parts {
+        wanted |= match part.to_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "shift" => Modifiers::SHIFT,
+            "alt" => Modifiers::ALT,
+            "logo" | "cmd" | "super" => Modifiers::LOGO,
+            _ => return false,
+        };
+    }
+    let pressed = match key {
+        Key::Character(c) => c.to_string(),
+        Key::Named(named) => format!("{named:?}"),
+        Key::Unidentified => return false,
+    };
+    !name.is_empty() && modifiers == wanted && pressed.eq_ignore_ascii_case(name)
+}
+#[cfg(test)]
+mod tests {
+    use iced::keyboard::{Key, Modifiers, key::Named};
+
+    use crate::app::keys::{KeyAction, KeyBindings};
+
+    #[test]
+    fn bindings_match() {
+        let mut keys = KeyBindings::default();
+        let none = Modifiers::empty();
+        let down = Key::Named(Named::ArrowDown);
+        assert_eq!(keys.action(&down, none), Some(KeyAction::NextSong));
+        assert_eq!(keys.action(&down, Modifiers::CTRL), None);
+
+        let z = Key::Character("z".into());
+        assert_eq!(keys.action(&z, Modifiers::CTRL), Some(KeyAction::Undo));
+        assert_eq!(keys.action(&z, none), None);
+        assert_eq!(
+            keys.action(&Key::Character("T".into()), none),
+            Some(KeyAction::ToggleTag)
+        );
+
+        keys.next_song = "shift+J".to_string();
+        keys.skip = String::new();
+        let j = Key::Character("j".into());
+        assert_eq!(keys.action(&j, Modifiers::SHIFT), Some(KeyAction::NextSong));
+        assert_eq!(keys.action(&down, none), None);
+        assert_eq!(keys.action(&Key::Named(Named::Delete), none), None);
+    }
+}
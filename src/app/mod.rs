@@ -1,8 +1,17 @@
+pub mod album;
+pub mod auto;
+pub mod config;
+pub mod crop;
+pub mod encode;
 pub mod iced_app;
 pub mod img;
 pub mod img_group;
+pub mod keys;
+mod phash;
+pub mod resize;
 pub mod song;
 pub mod song_view;
 mod styles;
 pub mod tags;
 mod view;
+pub mod weights;
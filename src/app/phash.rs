@@ -0,0 +1,125 @@
+//! Difference hash of an image, close hashes are the same picture at another size or quality
+use image::{DynamicImage, imageops::FilterType::Triangle};
+
+/// Hashes at most this many bits apart are one group, tuned on the fixtures in tests
+pub const HASH_THRESHOLD: u32 = 8;
+
+/// One bit per neighbour pair of a 9x8 luma thumbnail, set when brightness rises to the right
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let rises = small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | rises as u64;
+        }
+    }
+    hash
+}
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+#[cfg(test)]
+pub mod tests {
+    use std::{io::Cursor, time::Instant};
+
+    use image::{
+        DynamicImage, ImageFormat, Rgb, RgbImage, codecs::jpeg::JpegEncoder,
+        imageops::FilterType::Triangle,
+    };
+    use image_compare::{Algorithm::MSSIMSimple, gray_similarity_structure};
+
+    use crate::app::phash::{HASH_THRESHOLD, dhash, distance};
+
+    /// Cover like picture: gradient background, a disc and a bar placed by `seed`
+    pub fn cover(seed: u32, size: u32) -> DynamicImage {
+        let img = RgbImage::from_fn(size, size, |x, y| {
+            let (fx, fy) = (x as f32 / size as f32, y as f32 / size as f32);
+            let (cx, cy) = (0.3 + (seed % 3) as f32 * 0.2, 0.3 + (seed % 5) as f32 * 0.1);
+            let disc = (fx - cx).powi(2) + (fy - cy).powi(2) < 0.04;
+            let bar = (fy - (seed % 7) as f32 / 8.0).abs() < 0.05;
+            let base = ((fx * 120.0 + fy * 60.0) as u32 + seed * 37) % 255;
+            match (disc, bar) {
+                (true, _) => Rgb([230, 200 - (seed * 20 % 150) as u8, 40]),
+                (_, true) => Rgb([20, 20, 20]),
+                _ => Rgb([base as u8, (255 - base) as u8, (base / 2) as u8]),
+            }
+        });
+        DynamicImage::ImageRgb8(img)
+    }
+    fn jpeg(img: &DynamicImage, quality: u8) -> DynamicImage {
+        let mut bytes = Vec::new();
+        img.to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))
+            .unwrap();
+        image::load(Cursor::new(bytes), ImageFormat::Jpeg).unwrap()
+    }
+
+    fn fixture(bytes: &[u8]) -> u64 {
+        dhash(&image::load_from_memory(bytes).unwrap())
+    }
+
+    /// One painting served by two cdns and a reissue with its own crop and label
+    #[test]
+    fn fixture_covers() {
+        let cdn_a = fixture(include_bytes!("../../resources/fixtures/covers/cdn_a.jpg"));
+        let cdn_b = fixture(include_bytes!("../../resources/fixtures/covers/cdn_b.jpg"));
+        let pressing = fixture(include_bytes!(
+            "../../resources/fixtures/covers/pressing.jpg"
+        ));
+        assert!(distance(cdn_a, cdn_b) <= HASH_THRESHOLD);
+        assert!(distance(cdn_a, pressing) > HASH_THRESHOLD);
+        assert!(distance(cdn_b, pressing) > HASH_THRESHOLD);
+    }
+    #[test]
+    fn same_cover_close() {
+        for seed in 0..6 {
+            let orig = cover(seed, 600);
+            let hash = dhash(&orig);
+            let variants = [
+                orig.resize(150, 150, Triangle),
+                jpeg(&orig, 30),
+                orig.brighten(20),
+                orig.crop_imm(6, 6, 588, 588),
+            ];
+            for variant in variants {
+                let d = distance(hash, dhash(&variant));
+                assert!(d <= HASH_THRESHOLD);
+            }
+        }
+    }
+    #[test]
+    fn other_cover_far() {
+        for seed in 0..6 {
+            let hash = dhash(&cover(seed, 300));
+            let other = dhash(&cover(seed + 1, 300));
+            let flipped = dhash(&cover(seed, 300).fliph());
+            assert!(distance(hash, other) > HASH_THRESHOLD);
+            assert!(distance(hash, flipped) > HASH_THRESHOLD);
+        }
+    }
+    /// `cargo test --release -- --ignored --nocapture compare_speed`
+    #[test]
+    #[ignore]
+    fn compare_speed() {
+        let samples: Vec<_> = (0..40).map(|seed| cover(seed, 200).into_luma8()).collect();
+        let hashes: Vec<_> = (0..40).map(|seed| dhash(&cover(seed, 200))).collect();
+
+        let now = Instant::now();
+        for a in &samples {
+            for b in &samples {
+                gray_similarity_structure(&MSSIMSimple, a, b).unwrap();
+            }
+        }
+        let mssim = now.elapsed();
+        let now = Instant::now();
+        let mut close = 0;
+        for a in &hashes {
+            for b in &hashes {
+                close += (distance(*a, *b) <= HASH_THRESHOLD) as u32;
+            }
+        }
+        let hamming = now.elapsed();
+        println!("1600 pairs, mssim: {mssim:?}, hamming: {hamming:?}, {close} close");
+    }
+}
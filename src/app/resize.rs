@@ -0,0 +1,147 @@
+//! Final size of the cover: which side is measured, the filter and upscaling of tiny art
+use image::{DynamicImage, GenericImageView, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+
+use crate::app::img::ImageSettings;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Triangle,
+    CatmullRom,
+    Lanczos3,
+}
+impl ResizeFilter {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Triangle => "triangle",
+            Self::CatmullRom => "catmull-rom",
+            Self::Lanczos3 => "lanczos3",
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Self::Nearest => Self::Triangle,
+            Self::Triangle => Self::CatmullRom,
+            Self::CatmullRom => Self::Lanczos3,
+            Self::Lanczos3 => Self::Nearest,
+        }
+    }
+    pub fn imageops(self) -> FilterType {
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Triangle => FilterType::Triangle,
+            Self::CatmullRom => FilterType::CatmullRom,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Side compared with `downscale` and `min_size`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeMode {
+    #[default]
+    Height,
+    Width,
+    LongestEdge,
+}
+impl ResizeMode {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Height => "height",
+            Self::Width => "width",
+            Self::LongestEdge => "longest edge",
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Self::Height => Self::Width,
+            Self::Width => Self::LongestEdge,
+            Self::LongestEdge => Self::Height,
+        }
+    }
+    fn measure(self, (w, h): (u32, u32)) -> u32 {
+        match self {
+            Self::Height => h,
+            Self::Width => w,
+            Self::LongestEdge => w.max(h),
+        }
+    }
+}
+
+/// Size of a `dims` image after the settings, aspect is kept.
+/// Shrinks to `downscale`, grows to `min_size` but never past `downscale`, 0 turns either off
+pub fn target((w, h): (u32, u32), set: &ImageSettings) -> (u32, u32) {
+    let measured = set.resize_mode.measure((w, h));
+    if measured == 0 {
+        return (w, h);
+    }
+    let cap = if set.downscale == 0 {
+        u32::MAX
+    } else {
+        set.downscale
+    };
+    let goal = if measured > cap {
+        cap
+    } else if measured < set.min_size {
+        set.min_size.min(cap)
+    } else {
+        return (w, h);
+    };
+    let scale = goal as f64 / measured as f64;
+    let side = |s: u32| ((s as f64 * scale).round() as u32).max(1);
+    (side(w), side(h))
+}
+/// Image of `dims` would be made larger than it is
+pub fn upscales(dims: (u32, u32), set: &ImageSettings) -> bool {
+    target(dims, set).0 > dims.0
+}
+pub fn resize(img: DynamicImage, set: &ImageSettings) -> DynamicImage {
+    let dims = img.dimensions();
+    let (w, h) = target(dims, set);
+    if (w, h) == dims {
+        img
+    } else {
+        img.resize_exact(w, h, set.filter.imageops())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        img::ImageSettings,
+        resize::{ResizeMode, target, upscales},
+    };
+
+    #[test]
+    fn resize_modes() {
+        let mut set = ImageSettings {
+            downscale: 1000,
+            ..Default::default()
+        };
+        assert_eq!(target((3000, 1500), &set), (2000, 1000));
+        set.resize_mode = ResizeMode::Width;
+        assert_eq!(target((3000, 1500), &set), (1000, 500));
+        set.resize_mode = ResizeMode::LongestEdge;
+        assert_eq!(target((1500, 3000), &set), (500, 1000));
+        assert_eq!(target((800, 600), &set), (800, 600));
+        set.downscale = 0;
+        assert_eq!(target((3000, 1500), &set), (3000, 1500));
+    }
+    #[test]
+    fn tiny_covers_upscaled() {
+        let mut set = ImageSettings {
+            downscale: 1000,
+            ..Default::default()
+        };
+        assert!(!upscales((300, 300), &set));
+        set.min_size = 500;
+        assert_eq!(target((300, 300), &set), (500, 500));
+        assert!(upscales((300, 300), &set));
+        assert!(!upscales((600, 600), &set));
+        // never past the downscale size
+        set.min_size = 2000;
+        assert_eq!(target((300, 300), &set), (1000, 1000));
+    }
+}
@@ -2,8 +2,8 @@ use rand::RngCore;
 
 use crate::{
     ImgHandle, TaskHandle,
-    api::queue::Queue,
     app::{
+        album::AlbumId,
         img::{ImgId, SongImg},
         img_group::ImgGroups,
         tags::{SelectedTags, Tag, Tags},
@@ -47,6 +47,8 @@ pub type SongId = usize;
 /// * `imgs`: only push() or empty()
 /// * `tags_from_regex`: tags from regex to add to new_tags list
 /// * every time confirm is pressed
+/// * `album`: set when other loaded tracks share artist and album
+/// * `review`: confidence of the pick auto mode left for the user
 pub struct Song {
     pub tag_data: TagData,
     pub state: SongState,
@@ -63,6 +65,14 @@ pub struct Song {
     pub new_tags: Tags,
     pub tags_from_regex: Vec<Tag>,
     pub selected_tags: SelectedTags,
+    pub album: Option<AlbumId>,
+    pub review: Option<u32>,
+    /// images sent by the queue that are still being decoded
+    pub decoding: usize,
+    /// auto mode decided on the current search
+    pub judged: bool,
+    /// resolutions that came before their preview was decoded
+    pub probed: Vec<(String, (u32, u32))>,
 }
 
 impl Song {
@@ -76,17 +86,36 @@ impl Song {
             state: SongState::Confirm,
             queue_handle: None,
             original_art,
-original_art_hovered: false,
+            original_art_hovered: false,
             tag_data,
             hash: rand::rng().next_u64(),
             menu_img: None,
             selected_img: None,
-            sources_finished: (0, Queue::TOTAL_SOURCES),
+            sources_finished: (0, 0),
             img_groups: ImgGroups::new(),
             imgs: Vec::new(),
             new_tags: Tags::new(),
             tags_from_regex: Vec::new(),
             selected_tags: SelectedTags::new(),
+            album: None,
+            review: None,
+            decoding: 0,
+            judged: false,
+            probed: Vec::new(),
+        }
+    }
+    /// Probed full size of a preview, ranks it again or waits for it to be decoded
+    pub fn set_resolution(&mut self, url: String, res: (u32, u32)) {
+        match self
+            .imgs
+            .iter_mut()
+            .find(|img| img.preview_url() == Some(url.as_str()))
+        {
+            Some(img) => {
+                img.orig_res = Some(res);
+                self.img_groups.resort(&self.imgs);
+            }
+            None => self.probed.push((url, res)),
         }
     }
 
@@ -94,10 +123,13 @@ original_art_hovered: false,
         self.queue_handle.take().unwrap().abort();
         self.imgs.clear();
         self.img_groups.clear();
+        self.probed.clear();
         self.selected_img = None;
         self.menu_close();
         self.selected_tags.reset();
         self.new_tags.sorted.clear();
+        self.review = None;
+        self.judged = false;
     }
     pub fn menu_close(&mut self) {
         self.menu_img = None;
@@ -1,12 +1,14 @@
 use crate::{
     ImgHandle,
     app::{
+        album,
+        crop::SquareFill,
         iced_app::{CoverUI, Message, song_is_invalid},
         img::ImgId,
         song::{OrigArt, SongId, SongState},
         styles::{
-            button_st, filler_st, image_hover_st, image_selected_st, img_scroll_st, input_st,
-            item_cont_st, select_menu_st, tag_st,
+            button_st, filler_st, focused_item_st, image_hover_st, image_selected_st,
+            img_scroll_st, input_st, item_cont_st, select_menu_st, tag_st, upscale_badge_st,
         },
         view::{BTN_HEIGHT, INNER_TEXT_SIZE, TEXT_SIZE},
     },
@@ -38,14 +40,18 @@ const TAG_H: f32 = 30.0;
 const TAG_SPACING: f32 = 10.0;
 const INFO_LINE_H: f32 = 1.6;
 const CENTER_OFFSET: f32 = 1500.0;
+pub const LIST_PADDING: f32 = 8.0;
+pub const LIST_SPACING: f32 = 5.0;
 
 pub fn generate_view_list(ui: &CoverUI) -> iced::widget::Column<'_, Message> {
-    let list = column![].padding(8).spacing(5);
+    let list = column![].padding(LIST_PADDING).spacing(LIST_SPACING);
 
     // Calculate list height beforehand
     let mut real_h = 0.0;
     for i in 0..ui.state.songs.len() {
-        real_h += ui.state.songs[i].state.state_to_h();
+        if ui.state.is_listed(i) {
+            real_h += ui.state.songs[i].state.state_to_h();
+        }
     }
     let pos = ui.state.list_scroll;
     let center = real_h * pos;
@@ -57,7 +63,7 @@ pub fn generate_view_list(ui: &CoverUI) -> iced::widget::Column<'_, Message> {
     let mut sub_list: Vec<iced::Element<'_, _, _, _>> = Vec::with_capacity(ui.state.songs.len());
     for i in 0..ui.state.songs.len() {
         let h = ui.state.songs[i].state.state_to_h();
-        if h > 0.0 {
+        if h > 0.0 && ui.state.is_listed(i) {
             if real_h < start || real_h > end {
                 sub_list.push(generate_list_item(i, ui, true).into());
             } else {
@@ -165,6 +171,23 @@ pub fn generate_list_item<'a>(
     .line_height(INFO_LINE_H)
     .size(TEXT_SIZE);
 
+    let review_label = this.review.map(|confidence| {
+        text(format!("needs review, confidence {confidence}%"))
+            .height(BTN_HEIGHT)
+            .line_height(INFO_LINE_H)
+            .color(palette.warning.base.color)
+            .size(TEXT_SIZE)
+    });
+    let album_label = this.album.map(|album_id| {
+        text(format!(
+            "applies to {} tracks",
+            ui.state.albums[album_id].members.len()
+        ))
+        .height(BTN_HEIGHT)
+        .line_height(INFO_LINE_H)
+        .size(TEXT_SIZE)
+    });
+
     let path_label = text("path:")
         .size(TEXT_SIZE)
         .height(BTN_HEIGHT)
@@ -176,6 +199,18 @@ pub fn generate_list_item<'a>(
         .line_height(INFO_LINE_H)
         .size(TEXT_SIZE);
     let btn = |s| button(h3(s).center()).clip(true).height(BTN_HEIGHT);
+    // followers are confirmed with the album, detaching gives them their own search
+    let confirm = if album::follows(&ui.state.songs, &ui.state.albums, id).is_some() {
+        btn("detach")
+            .width(Fill)
+            .style(button_st)
+            .on_press(DetachFromAlbum(id))
+    } else {
+        btn("confirm")
+            .width(Fill)
+            .style(button_st)
+            .on_press(ConfirmSongIfNot(id))
+    };
 
     let cont = match this.state {
         SongState::Confirm => container(
@@ -214,6 +249,14 @@ pub fn generate_list_item<'a>(
                                     space().width(INFO_COLUMN_GAP).height(1),
                                     orig_img(ui, id, art)
                                 ]
+                                .push(this.tag_data.provenance.as_ref().map(|p| {
+                                    text(p.summary())
+                                        .size(INNER_TEXT_SIZE)
+                                        .color(palette.background.strong.text)
+                                        .wrapping(text::Wrapping::None)
+                                        .width(260)
+                                }))
+                                .spacing(INFO_COLUMN_GAP)
                             } else if *cover == OrigArt::Loading {
                                 row![]
                             } else {
@@ -229,10 +272,7 @@ pub fn generate_list_item<'a>(
                 ]
                 .spacing(INFO_COLUMN_GAP),
                 column![
-                    btn("confirm")
-                        .width(Fill)
-                        .style(button_st)
-                        .on_press(ConfirmSongIfNot(id)),
+                    confirm,
                     btn("remove")
                         .width(Fill)
                         .style(button_st)
@@ -250,7 +290,12 @@ pub fn generate_list_item<'a>(
             row![
                 Column::new()
                     .push(image_row(ui, id))
-                    .push(row![sources_label, sources].spacing(INFO_ROW_GAP))
+                    .push(
+                        row![sources_label, sources]
+                            .push(album_label)
+                            .push(review_label)
+                            .spacing(INFO_ROW_GAP)
+                    )
                     .push(
                         text("update tags:")
                             .size(TEXT_SIZE)
@@ -308,8 +353,13 @@ pub fn generate_list_item<'a>(
         .height(MAIN_H),
         SongState::Hidden => panic!("Cannot draw hidden song"),
     };
+    let style = if ui.state.focus == Some(id) {
+        focused_item_st
+    } else {
+        item_cont_st
+    };
     row![
-        cont.style(item_cont_st).width(Fill).padding(10),
+        cont.style(style).width(Fill).padding(10),
         space().width(20).height(20)
     ]
 }
@@ -357,7 +407,9 @@ fn image_box<'a>(ui: &CoverUI, id: SongId, img_iter: ImgId) -> MouseArea<'a, Mes
     let border = this.selected_img == Some(img_iter);
     let mut info_col = Column::new().spacing(INFO_ROW_GAP - 5.0);
 
-    let strategy = if ui.state.img_settings.square {
+    // padded images show whole
+    let set = &ui.state.img_settings;
+    let strategy = if set.square && set.square_fill == SquareFill::Crop {
         iced::ContentFit::Cover
     } else {
         iced::ContentFit::Contain
@@ -378,7 +430,7 @@ fn image_box<'a>(ui: &CoverUI, id: SongId, img_iter: ImgId) -> MouseArea<'a, Mes
     );
     let mut feedback = img.feedback.to_string();
     feedback.push_str("\n img weight: ");
-    feedback.push_str(&img.src.get_weight().to_string());
+    feedback.push_str(&img.score().to_string());
     info_col = info_col.push(
         text(feedback)
             .size(INNER_TEXT_SIZE)
@@ -399,6 +451,17 @@ fn image_box<'a>(ui: &CoverUI, id: SongId, img_iter: ImgId) -> MouseArea<'a, Mes
                 .height(ART_WH),
         )
         .padding(10),
+        // final image would be larger than the art
+        if img.upscaled(set) {
+            container(
+                container(text("upscaled").size(INNER_TEXT_SIZE))
+                    .padding([2, 6])
+                    .style(upscale_badge_st),
+            )
+            .padding(14)
+        } else {
+            container(space())
+        },
         if this.menu_img == Some(img_iter) {
             center(
                 container(
@@ -408,14 +471,21 @@ fn image_box<'a>(ui: &CoverUI, id: SongId, img_iter: ImgId) -> MouseArea<'a, Mes
                                 button(text("select").size(INNER_TEXT_SIZE).center())
                                     .on_press(Message::ImgSelect(id, img_iter))
                                     .height(BTN_HEIGHT)
-                                    .width(70)
+                                    .width(90)
                                     .style(button_st),
                                 button(text("preview").size(INNER_TEXT_SIZE).center())
                                     .on_press(Message::ImgPreviewOpen(id, img_iter))
                                     .height(BTN_HEIGHT)
-                                    .width(70)
+                                    .width(90)
                                     .style(button_st),
                             ]
+                            .push(img.release.as_ref().map(|_| {
+                                button(text("tag release").size(INNER_TEXT_SIZE).center())
+                                    .on_press(Message::TagFromRelease(id, img_iter))
+                                    .height(BTN_HEIGHT)
+                                    .width(90)
+                                    .style(button_st)
+                            }))
                             .spacing(INFO_ROW_GAP)
                         )
                         .width(Fill),
@@ -485,8 +555,9 @@ fn tag<'a>(ui: &CoverUI, id: SongId, tag_iter: ImgId) -> Button<'a, Message> {
     let label = format!("{}: {}", tag.key.to_label(), tag.value);
     let key = tag.key;
     let selected = this.selected_tags.is_select(tag.key, &tag.value);
+    let focused = ui.state.focus == Some(id) && ui.state.focus_tag == tag_iter;
     button(text(label).size(INNER_TEXT_SIZE))
-        .style(move |theme, status| tag_st(theme, status, key, selected))
+        .style(move |theme, status| tag_st(theme, status, key, selected, focused))
         .on_press(Message::TagToggle(id, tag_iter))
         .height(TAG_H)
 }
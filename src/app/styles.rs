@@ -219,6 +219,20 @@ pub fn image_hover_st(theme: &Theme) -> container::Style {
         ..container::Style::default()
     }
 }
+pub fn upscale_badge_st(theme: &Theme) -> container::Style {
+    let p = theme.extended_palette();
+
+    container::Style {
+        background: Some(p.warning.base.color.into()),
+        border: Border {
+            width: 0.0,
+            radius: 5.0.into(),
+            color: p.warning.base.color,
+        },
+        text_color: Some(p.warning.base.text),
+        ..container::Style::default()
+    }
+}
 pub fn select_menu_st(theme: &Theme) -> container::Style {
     let p = theme.extended_palette();
 
@@ -247,6 +261,19 @@ pub fn preview_box_st(theme: &Theme) -> container::Style {
         ..container::Style::default()
     }
 }
+pub fn crop_rect_st(theme: &Theme) -> container::Style {
+    let p = theme.extended_palette();
+
+    container::Style {
+        background: Some(Color::from_rgba8(255, 255, 255, 0.1).into()),
+        border: Border {
+            width: 2.0,
+            radius: 0.0.into(),
+            color: p.primary.base.color,
+        },
+        ..container::Style::default()
+    }
+}
 pub fn list_bg_st(theme: &Theme) -> container::Style {
     let p = theme.extended_palette();
 
@@ -448,11 +475,25 @@ pub fn item_cont_st(theme: &Theme) -> container::Style {
         ..container::Style::default()
     }
 }
+pub fn focused_item_st(theme: &Theme) -> container::Style {
+    let palette = theme.extended_palette();
+
+    container::Style {
+        background: Some(palette.background.base.color.into()),
+        border: Border {
+            width: 2.0,
+            radius: 10.0.into(),
+            color: palette.primary.base.color,
+        },
+        ..container::Style::default()
+    }
+}
 pub fn tag_st(
     theme: &Theme,
     status: button::Status,
     tag_type: TagType,
     selected: bool,
+    focused: bool,
 ) -> button::Style {
     let palette = theme.extended_palette();
 
@@ -482,7 +523,7 @@ pub fn tag_st(
         text_color,
         background,
         border: Border {
-            width: 1.0,
+            width: if focused { 3.0 } else { 1.0 },
             radius: 100.0.into(),
             color,
         },
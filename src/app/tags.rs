@@ -1,10 +1,10 @@
 use std::array::from_fn;
 
-use crate::parser::file_parser::TagData;
+use crate::parser::{file_parser::TagData, journal::Journal};
 
 pub const USER_INPUT_TAG_SCORE: i32 = 100;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TagType {
     Artist,
     Album,
@@ -54,31 +54,39 @@ impl SelectedTags {
             self.types[key as usize] = value;
         }
     }
-    pub fn apply_selected(&mut self, tag_data: &mut TagData) -> Result<(), anyhow::Error> {
-        let mut write = false;
-        if let Some(album) = self.types[TagType::Album as usize].take() {
-            write = true;
-            tag_data.file.set_album_title(&album);
-            tag_data.album = if album.is_empty() { None } else { Some(album) };
+    pub fn apply_selected(
+        &mut self,
+        tag_data: &mut TagData,
+        journal: &mut Journal,
+    ) -> Result<(), anyhow::Error> {
+        let album = self.types[TagType::Album as usize].take();
+        let title = self.types[TagType::Title as usize].take();
+        let artist = self.types[TagType::Artist as usize].take();
+        if album.is_none() && title.is_none() && artist.is_none() {
+            return Ok(());
         }
-        if let Some(title) = self.types[TagType::Title as usize].take() {
-            write = true;
-            tag_data.file.set_title(&title);
-            tag_data.title = if title.is_empty() { None } else { Some(title) };
+        if let Some(album) = &album {
+            tag_data.file.set_album_title(album);
         }
-        if let Some(artist) = self.types[TagType::Artist as usize].take() {
-            write = true;
-            tag_data.file.set_artist(&artist);
-            tag_data.artist = if artist.is_empty() {
-                None
-            } else {
-                Some(artist)
-            };
+        if let Some(title) = &title {
+            tag_data.file.set_title(title);
         }
-        if write {
-            tag_data
-                .file
-                .write_to_path(tag_data.path.to_str().unwrap())?;
+        if let Some(artist) = &artist {
+            tag_data.file.set_artist(artist);
+        }
+        journal.write_tags(&tag_data.path, &mut tag_data.file, "update tags")?;
+        if journal.dry_run {
+            return Ok(());
+        }
+        let value = |v: String| if v.is_empty() { None } else { Some(v) };
+        if let Some(album) = album {
+            tag_data.album = value(album);
+        }
+        if let Some(title) = title {
+            tag_data.title = value(title);
+        }
+        if let Some(artist) = artist {
+            tag_data.artist = value(artist);
         }
         Ok(())
     }
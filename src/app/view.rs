@@ -5,23 +5,28 @@ use iced::{
     Element,
     Length::{Fill, FillPortion},
     alignment::Horizontal::{self},
+    mouse::ScrollDelta,
     widget::{
-        column, container,
+        center, column, container, image,
         image::Viewer,
-        row,
+        mouse_area, pin, responsive, row,
         scrollable::{Direction, Scrollbar},
-        space, toggler,
+        slider, space, toggler,
     },
 };
 use log::info;
 
 use crate::{
     ImgHandle,
-    app::{iced_app::Message, img::ImgId, song::SongId, song_view},
+    app::{crop::CropRect, iced_app::Message, img::ImgId, song::SongId, song_view},
 };
 use crate::{
     TaskHandle,
-    app::{iced_app::CoverUI, song::Song, styles::*},
+    app::{
+        iced_app::CoverUI,
+        song::{Song, SongState},
+        styles::*,
+    },
 };
 use iced::widget::scrollable;
 use iced::widget::{button, checkbox, stack, text, text_input};
@@ -33,6 +38,11 @@ pub const H1_SIZE: f32 = 17.0;
 pub const INNER_TEXT_SIZE: f32 = 14.0;
 pub const BTN_HEIGHT: f32 = 25.0;
 pub const HEADER_H: f32 = 200.0;
+pub const LIST_ID: &str = "song_list";
+/// Crop moved by one nudge, part of the image side
+const NUDGE_STEP: f32 = 0.01;
+/// Crop side change of one scroll step
+const CROP_ZOOM: f32 = 1.05;
 
 #[derive(Clone, Debug, Default)]
 pub enum PreviewState {
@@ -41,9 +51,53 @@ pub enum PreviewState {
     Loading,
     Error,
     Display(ImgHandle, SongId, ImgId),
+    /// Turned and flipped image with the full size of it
+    Editing(ImgHandle, (u32, u32), SongId, ImgId),
     Downloading(TaskHandle),
 }
 
+/// Image fitted to the available space with the crop square over it,
+/// pressing or dragging moves the square there, scrolling resizes it
+fn crop_editor<'a>(handle: &ImgHandle, dims: (u32, u32), crop: CropRect) -> Element<'a, Message> {
+    use Message::*;
+    let handle = handle.clone();
+    responsive(move |size| {
+        let (w, h) = (dims.0 as f32, dims.1 as f32);
+        let scale = (size.width / w).min(size.height / h);
+        let (dw, dh) = (w * scale, h * scale);
+        let (x, y, side) = crop.pixels(dims);
+        let side = side as f32 * scale;
+
+        let area = mouse_area(space().width(dw).height(dh))
+            .on_press(CropDrag(true))
+            .on_release(CropDrag(false))
+            .on_exit(CropDrag(false))
+            .on_move(move |p| CropMove(p.x / dw, p.y / dh))
+            .on_scroll(|delta| {
+                let (ScrollDelta::Lines { y, .. } | ScrollDelta::Pixels { y, .. }) = delta;
+                CropResize(if y < 0.0 { CROP_ZOOM } else { 1.0 / CROP_ZOOM })
+            });
+        center(
+            stack![
+                image(handle.clone()).width(dw).height(dh),
+                pin(container(space())
+                    .width(side)
+                    .height(side)
+                    .style(crop_rect_st))
+                .x(x as f32 * scale)
+                .y(y as f32 * scale)
+                .width(dw)
+                .height(dh),
+                area,
+            ]
+            .width(dw)
+            .height(dh),
+        )
+        .into()
+    })
+    .into()
+}
+
 pub fn view(ui: &CoverUI) -> Element<'_, Message> {
     use Message::*;
 
@@ -164,8 +218,34 @@ pub fn view(ui: &CoverUI) -> Element<'_, Message> {
         ]
         .spacing(10),
         regex.wrap(),
+        row![
+            checkbox(ui.state.parse_settings.only_problems)
+                .on_toggle(|_| ProblemsToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+            h2("only art problems, min"),
+            text_input("", &ui.state.parse_settings.min_art_size.to_string())
+                .style(input_st)
+                .width(60)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(MinArtInput),
+            h2("px"),
+        ]
+        .spacing(10),
     ]
     .spacing(10);
+    let max_kb = match ui.state.img_settings.max_kb {
+        0 => String::new(),
+        kb => kb.to_string(),
+    };
+    let min_size = match ui.state.img_settings.min_size {
+        0 => String::new(),
+        size => size.to_string(),
+    };
+    let review_count = (ui.state.songs.iter())
+        .filter(|s| s.state != SongState::Hidden && s.review.is_some())
+        .count();
     let settings_panel = column![
         text("Settings")
             .size(H1_SIZE)
@@ -173,33 +253,136 @@ pub fn view(ui: &CoverUI) -> Element<'_, Message> {
             .align_x(Alignment::Center)
             .color(header_color),
         row![
-            h2("downscale (height)"),
-            container("").width(10),
+            h2("downscale"),
+            btn(ui.state.img_settings.resize_mode.to_str())
+                .width(90)
+                .height(BTN_HEIGHT)
+                .style(button_st)
+                .on_press(ResizeModePressed),
             text_input("", &ui.state.img_settings.downscale.to_string())
                 .style(input_st)
                 .width(60)
                 .align_x(Alignment::Center)
                 .size(INNER_TEXT_SIZE)
                 .on_input(DownscaleInput),
-            container("").width(3),
             h2("px"),
-        ],
+        ]
+        .spacing(10),
         row![
-            h2("crop to square (width)"),
+            h2("upscale below"),
+            text_input("off", &min_size)
+                .style(input_st)
+                .width(60)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(MinSizeInput),
+            h2("px"),
+            h2("filter"),
+            btn(ui.state.img_settings.filter.to_str())
+                .width(90)
+                .height(BTN_HEIGHT)
+                .style(button_st)
+                .on_press(FilterTypePressed),
+        ]
+        .spacing(10),
+        row![
+            h2("make square"),
             checkbox(ui.state.img_settings.square)
                 .on_toggle(|_| SquareToggle)
                 .size(BTN_HEIGHT)
                 .style(check_st),
+            btn(ui.state.img_settings.square_fill.to_str())
+                .width(60)
+                .height(BTN_HEIGHT)
+                .style(button_st)
+                .on_press(SquareFillPressed),
+        ]
+        .spacing(10),
+        row![
+            h2("trim letterbox bars"),
+            checkbox(ui.state.img_settings.trim_borders)
+                .on_toggle(|_| TrimToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+        ]
+        .spacing(10),
+        row![
+            h2("format"),
+            btn(ui.state.img_settings.format.to_str())
+                .width(60)
+                .height(BTN_HEIGHT)
+                .style(button_st)
+                .on_press(FormatPressed),
+            h2("quality"),
+            slider(1..=100, ui.state.img_settings.jpeg_quality, QualityInput).width(100),
+            text(ui.state.img_settings.jpeg_quality)
+                .size(TEXT_SIZE)
+                .line_height(1.7),
+        ]
+        .align_y(Alignment::Center)
+        .spacing(10),
+        row![
+            h2("max size"),
+            text_input("none", &max_kb)
+                .style(input_st)
+                .width(60)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(MaxKbInput),
+            h2("KB"),
+            h2("optimize png"),
+            checkbox(ui.state.img_settings.optimize_png)
+                .on_toggle(|_| OptimizePngToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+        ]
+        .spacing(10),
+        row![
+            h2("strip metadata"),
+            checkbox(ui.state.img_settings.strip_metadata)
+                .on_toggle(|_| StripMetaToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
         ]
         .spacing(10),
         row![
-            h2("convert to jpg"),
-            checkbox(ui.state.img_settings.jpg)
-                .on_toggle(|_| JpgToggle)
+            h2("write cover"),
+            btn(ui.state.apply_settings.target.to_str())
+                .width(60)
+                .height(BTN_HEIGHT)
+                .style(button_st)
+                .on_press(CoverTargetPressed),
+            text_input("cover.jpg", &ui.state.apply_settings.folder_file)
+                .style(input_st)
+                .width(100)
+                .size(INNER_TEXT_SIZE)
+                .on_input(FolderFileInput),
+        ]
+        .spacing(10),
+        row![
+            h2("keep .bak of tracks"),
+            checkbox(ui.state.journal.keep_backup)
+                .on_toggle(|_| BackupToggle)
                 .size(BTN_HEIGHT)
                 .style(check_st),
         ]
         .spacing(10),
+        row![
+            h2("dry run"),
+            checkbox(ui.state.journal.dry_run)
+                .on_toggle(|_| DryRunToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+            btn("undo last")
+                .width(90)
+                .style(button_st)
+                .on_press_maybe(ui.state.journal.can_undo().then_some(UndoLast)),
+            btn("undo session")
+                .width(90)
+                .style(button_st)
+                .on_press_maybe(ui.state.journal.can_undo().then_some(UndoSession)),
+        ]
+        .spacing(10),
         row![
             h2("start auto process"),
             toggler(ui.state.auto_mod)
@@ -208,19 +391,120 @@ pub fn view(ui: &CoverUI) -> Element<'_, Message> {
                 .style(toggler_st),
         ]
         .spacing(10),
+        row![
+            h2("apply above"),
+            text_input("", &ui.state.auto_settings.threshold.to_string())
+                .style(input_st)
+                .width(40)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(ThresholdInput),
+            h2("%, songs at once"),
+            text_input("", &ui.state.auto_settings.limit.to_string())
+                .style(input_st)
+                .width(40)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(AutoLimitInput),
+        ]
+        .spacing(10),
+        row![
+            checkbox(ui.state.review_only)
+                .on_toggle(|_| ReviewOnlyToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+            text(format!("only needs review ({review_count})"))
+                .size(TEXT_SIZE)
+                .line_height(1.7),
+        ]
+        .spacing(10),
     ]
     .spacing(10);
-    let header = row![
-        files_panel.height(Fill).width(FillPortion(1)),
+    let mut sources_list = column![].spacing(10);
+    for entry in ui.state.sources.entries() {
+        let kind = entry.kind;
+        sources_list = sources_list.push(
+            row![
+                checkbox(entry.enabled)
+                    .on_toggle(move |_| SourceToggle(kind))
+                    .size(BTN_HEIGHT)
+                    .style(check_st),
+                h2(kind.to_str()),
+            ]
+            .spacing(10),
+        );
+        let [album, title] = kind.sources();
+        let weight_input = |src| {
+            text_input("", &ui.state.weights.base(src).to_string())
+                .style(input_st)
+                .width(45)
+                .align_x(Alignment::Center)
+                .size(INNER_TEXT_SIZE)
+                .on_input(move |s| WeightInput(src, s))
+        };
+        sources_list = sources_list.push(
+            row![
+                h2("weight album"),
+                weight_input(album),
+                h2("title"),
+                weight_input(title),
+            ]
+            .spacing(10),
+        );
+        if let Some(label) = kind.key_label() {
+            let key = ui.state.sources.keys.get(kind).expect("source has key");
+            sources_list = sources_list.push(
+                text_input(label, key)
+                    .style(input_st)
+                    .size(INNER_TEXT_SIZE)
+                    .secure(!key.is_empty())
+                    .on_input(move |s| ApiKeyInput(kind, s)),
+            );
+        }
+    }
+    let sources_panel = column![
+        text("Sources")
+            .size(H1_SIZE)
+            .width(Fill)
+            .align_x(Alignment::Center)
+            .color(header_color),
+        scrollable(sources_list)
+            .direction(Direction::Vertical(
+                Scrollbar::new().margin(0).scroller_width(5),
+            ))
+            .height(Fill)
+            .style(list_scroll_st),
+        row![
+            btn("clear cache")
+                .width(90)
+                .style(button_st)
+                .on_press(ClearCache),
+            checkbox(ui.state.weights.learn)
+                .on_toggle(|_| LearnToggle)
+                .size(BTN_HEIGHT)
+                .style(check_st),
+            h2("learn from picks"),
+        ]
+        .spacing(10),
+    ]
+    .spacing(10);
+    let bar = || {
         container(container("").style(bar_st).width(1).height(Fill))
             .width(30)
             .height(Fill)
-            .padding(10),
-        settings_panel.width(Fill).height(Fill)
+            .padding(10)
+    };
+    let header = row![
+        files_panel.height(Fill).width(FillPortion(1)),
+        bar(),
+        settings_panel.width(Fill).height(Fill),
+        bar(),
+        sources_panel.width(FillPortion(1)).height(Fill),
     ];
 
     let list = song_view::generate_view_list(ui);
     let list = scrollable(list)
+        .id(LIST_ID)
         .auto_scroll(true)
         .direction(Direction::Vertical(
             Scrollbar::new().margin(0).scroller_width(15),
@@ -288,6 +572,10 @@ pub fn view(ui: &CoverUI) -> Element<'_, Message> {
                     container(match &ui.state.preview_img {
                         PreviewState::Display(h, _, _) =>
                             container(Viewer::new(h).height(Fill).width(Fill)),
+                        PreviewState::Editing(h, dims, song_id, img_id) => {
+                            let crop = ui.state.songs[*song_id].imgs[*img_id].edit.crop;
+                            container(crop_editor(h, *dims, crop.unwrap_or_default()))
+                        }
                         PreviewState::Error => container(
                             text("Error occurred")
                                 .center()
@@ -320,10 +608,39 @@ pub fn view(ui: &CoverUI) -> Element<'_, Message> {
                 match &ui.state.preview_img {
                     PreviewState::Display(_, song_id, img_id) => {
                         container(
-                            btn("save locally...")
-                                .width(110)
-                                .on_press(SaveImgLocally(*song_id, *img_id))
-                                .style(button_st),
+                            row![
+                                btn("save locally...")
+                                    .width(110)
+                                    .on_press(SaveImgLocally(*song_id, *img_id))
+                                    .style(button_st),
+                                btn("edit")
+                                    .width(50)
+                                    .on_press(EditPreview(true))
+                                    .style(button_st),
+                            ]
+                            .spacing(10),
+                        )
+                        .padding(10.0)
+                        .center_x(Fill)
+                    }
+                    PreviewState::Editing(..) => {
+                        let tool =
+                            |s, width, msg| btn(s).width(width).on_press(msg).style(button_st);
+                        container(
+                            row![
+                                tool("rotate", 60, EditRotate),
+                                tool("flip h", 50, EditFlip(true)),
+                                tool("flip v", 50, EditFlip(false)),
+                                tool("left", 45, CropNudge(-NUDGE_STEP, 0.0)),
+                                tool("up", 45, CropNudge(0.0, -NUDGE_STEP)),
+                                tool("down", 45, CropNudge(0.0, NUDGE_STEP)),
+                                tool("right", 45, CropNudge(NUDGE_STEP, 0.0)),
+                                tool("smaller", 60, CropResize(1.0 / CROP_ZOOM)),
+                                tool("larger", 60, CropResize(CROP_ZOOM)),
+                                tool("reset", 50, EditReset),
+                                tool("done", 50, EditPreview(false)),
+                            ]
+                            .spacing(10),
                         )
                         .padding(10.0)
                         .center_x(Fill)
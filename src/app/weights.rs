@@ -0,0 +1,145 @@
+//! Source weights from settings, plus what was picked before in the same library
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, anyhow};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::queue::Source, app::config::config_dir, parser::atomic};
+
+const STATS_FILE: &str = "stats.json";
+/// Picks in a library before they move weights
+const LEARN_MIN: u32 = 5;
+/// Weight added to a source that got every pick of a library
+const LEARN_LIM: u32 = 20;
+
+/// * `custom`: weights changed in settings, other sources keep `Source::get_weight`
+/// * `learn`: count applied picks and favor sources picked most
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceWeights {
+    pub custom: HashMap<Source, i32>,
+    pub learn: bool,
+}
+impl SourceWeights {
+    pub fn base(&self, src: Source) -> i32 {
+        self.custom.get(&src).copied().unwrap_or(src.get_weight())
+    }
+    pub fn set(&mut self, src: Source, weight: i32) {
+        if weight == src.get_weight() {
+            self.custom.remove(&src);
+        } else {
+            self.custom.insert(src, weight);
+        }
+    }
+    /// Weight of an image found for a song of `library`
+    pub fn weight(&self, stats: &PickStats, library: &Path, src: Source) -> i32 {
+        let learned = if self.learn {
+            stats.bonus(library, src)
+        } else {
+            0
+        };
+        self.base(src) + learned
+    }
+}
+
+/// Sources of applied images per library folder, kept next to the config
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PickStats {
+    libraries: HashMap<PathBuf, HashMap<Source, u32>>,
+}
+impl PickStats {
+    /// Never fails, starts over if the file is unreadable
+    pub fn load() -> Self {
+        Self::try_load()
+            .inspect_err(|e| warn!("pick stats were not loaded: {e}"))
+            .unwrap_or_default()
+    }
+    fn try_load() -> Result<Self, Error> {
+        let path = stats_path().ok_or(anyhow!("config dir is unknown"))?;
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(&path)?)?)
+    }
+    pub fn save(&self) {
+        let _ = self
+            .try_save()
+            .inspect_err(|e| warn!("pick stats were not saved: {e}"));
+    }
+    fn try_save(&self) -> Result<(), Error> {
+        let path = stats_path().ok_or(anyhow!("config dir is unknown"))?;
+        create_dir_all(path.parent().expect("stats file has parent"))?;
+        atomic::write_bytes(&path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+    /// Local files are always on top and are not counted
+    pub fn record(&mut self, library: &Path, src: Source) {
+        if src == Source::LocalFile {
+            return;
+        }
+        let picks = self.libraries.entry(library.to_path_buf()).or_default();
+        *picks.entry(src).or_default() += 1;
+    }
+    /// Share of the library picks that went to `src`, scaled up to `LEARN_LIM`
+    fn bonus(&self, library: &Path, src: Source) -> i32 {
+        let Some(picks) = self.libraries.get(library) else {
+            return 0;
+        };
+        let total: u32 = picks.values().sum();
+        if total < LEARN_MIN {
+            return 0;
+        }
+        let count = picks.get(&src).copied().unwrap_or_default();
+        (count * LEARN_LIM / total) as i32
+    }
+}
+fn stats_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(STATS_FILE))
+}
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        api::queue::Source::{BrainzAlbum, DeezerAlbum, LocalFile},
+        app::weights::{LEARN_LIM, PickStats, SourceWeights},
+    };
+
+    #[test]
+    fn learned_weights() {
+        let library = Path::new("/music");
+        let mut weights = SourceWeights::default();
+        weights.set(DeezerAlbum, 40);
+        weights.set(BrainzAlbum, BrainzAlbum.get_weight());
+        assert_eq!(weights.custom.len(), 1);
+
+        let mut stats = PickStats::default();
+        for _ in 0..4 {
+            stats.record(library, BrainzAlbum);
+            stats.record(library, LocalFile);
+        }
+        // too few picks to trust
+        weights.learn = true;
+        assert_eq!(weights.weight(&stats, library, BrainzAlbum), 30);
+
+        stats.record(library, BrainzAlbum);
+        assert_eq!(
+            weights.weight(&stats, library, BrainzAlbum),
+            30 + LEARN_LIM as i32
+        );
+        assert_eq!(weights.weight(&stats, library, DeezerAlbum), 40);
+        assert_eq!(weights.weight(&stats, Path::new("/other"), BrainzAlbum), 30);
+        weights.learn = false;
+        assert_eq!(weights.weight(&stats, library, BrainzAlbum), 30);
+
+        let json = serde_json::to_string(&stats).unwrap();
+        let back: PickStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.bonus(library, BrainzAlbum), LEARN_LIM as i32);
+    }
+}
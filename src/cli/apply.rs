@@ -0,0 +1,188 @@
+use std::{collections::HashMap, process::ExitCode, sync::Arc};
+
+use anyhow::{Error, bail};
+use iced::futures::{StreamExt, channel::mpsc};
+use log::warn;
+use reqwest::Client;
+use rfd::FileHandle;
+use tokio::sync::Semaphore;
+
+use crate::{
+    api::{
+        net,
+        queue::{Queue, QueueMessage, Source, TagsInput},
+        shared,
+    },
+    app::{
+        album::{self, AlbumId},
+        config::Config,
+        iced_app::Message,
+        img::{ImageProgress, ImgFormat, SongImg},
+        song::{Song, SongId},
+        weights::PickStats,
+    },
+    cli::ApplyArgs,
+    parser::{
+        file_parser::{self, Cover, ParseSettings, get_tags_data},
+        journal::Journal,
+    },
+};
+
+/// * `Applied`: with the cover for the rest of the album
+enum Outcome {
+    Applied(Source, Option<Box<Cover>>),
+    Picked(Source),
+    NotFound,
+}
+
+pub async fn apply(args: ApplyArgs) -> Result<ExitCode, Error> {
+    let config = Config::load();
+    let mut journal = Journal::new(args.dry_run, config.keep_backup);
+    let parse_settings = ParseSettings {
+        recursive: args.recursive,
+        ..config.parse_settings.clone()
+    };
+    let paths = args.paths.into_iter().map(FileHandle::from).collect();
+    let mut songs = get_tags_data(paths, parse_settings).await?;
+
+    let decode_sem = Arc::new(Semaphore::new(1));
+    let client = net::client();
+    let total = songs.len();
+    let (mut done, mut not_found, mut failed) = (0, 0, 0);
+
+    // like in the window, the first track of an album is searched and its pick is
+    // written into the rest. Tracks of an album whose first track found nothing search alone
+    let mut albums = Vec::new();
+    album::group_songs(&mut songs, 0, &mut albums);
+    let mut picks: HashMap<AlbumId, (Source, Option<Box<Cover>>)> = HashMap::new();
+
+    for id in 0..total {
+        let album_id = songs[id].album;
+        let leads = album_id.is_some() && album::follows(&songs, &albums, id).is_none();
+        let song = &mut songs[id];
+        let path = song.tag_data.path.display().to_string();
+        let pick = album_id.filter(|_| !leads).and_then(|a| picks.get_mut(&a));
+        let res = match pick {
+            Some((src, Some(cover))) => {
+                file_parser::write_cover(song, cover, &config.apply_settings, &mut journal)
+                    .map(|()| Outcome::Applied(*src, None))
+            }
+            Some((src, None)) => Ok(Outcome::Picked(*src)),
+            None => {
+                process_song(
+                    id,
+                    song,
+                    &config,
+                    &mut journal,
+                    args.auto,
+                    &client,
+                    decode_sem.clone(),
+                )
+                .await
+            }
+        };
+        match res {
+            Ok(Outcome::Applied(src, cover)) => {
+                if leads && let Some(album_id) = album_id {
+                    picks.insert(album_id, (src, cover));
+                }
+                done += 1;
+                let verb = if args.dry_run { "dry run" } else { "applied" };
+                println!("[{}/{total}] {path}: {verb} from {src}", id + 1);
+            }
+            Ok(Outcome::Picked(src)) => {
+                if leads && let Some(album_id) = album_id {
+                    picks.insert(album_id, (src, None));
+                }
+                done += 1;
+                println!("[{}/{total}] {path}: would apply from {src}", id + 1);
+            }
+            Ok(Outcome::NotFound) => {
+                not_found += 1;
+                println!("[{}/{total}] {path}: no image found", id + 1);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("[{}/{total}] {path}: failed, {e}", id + 1);
+            }
+        }
+    }
+
+    let verb = if args.auto { "applied" } else { "picked" };
+    println!("{total} files: {done} {verb}, {not_found} not found, {failed} failed");
+
+    if not_found + failed > 0 {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Run the full queue for one song, group results and write the first image of the top group
+async fn process_song(
+    id: SongId,
+    song: &mut Song,
+    config: &Config,
+    journal: &mut Journal,
+    auto: bool,
+    client: &Client,
+    decode_sem: Arc<Semaphore>,
+) -> Result<Outcome, Error> {
+    let tags = TagsInput::from_data(id, song.hash, &song.tag_data);
+    let (tx, rx) = mpsc::channel(20);
+    let queue = tokio::spawn(Queue::queue(tags, config.sources(), tx));
+
+    // drain everything first, queue drops messages when channel is full
+    let messages: Vec<Message> = rx.collect().await;
+    queue.await?;
+    let mut arts: Vec<SongImg> = Vec::new();
+    let mut probed = Vec::new();
+    for mes in messages {
+        match mes {
+            Message::FromQueue(_, _, QueueMessage::GotArt(img)) => arts.push(img),
+            Message::FromQueue(_, _, QueueMessage::Resolution(url, res)) => probed.push((url, res)),
+            _ => (),
+        }
+    }
+
+    let stats = PickStats::load();
+    for mut img in arts {
+        img.take_probed(&mut probed);
+        img.weight = config
+            .source_weights
+            .weight(&stats, &song.tag_data.library, img.src);
+        let res = img
+            .decode_and_sample(decode_sem.clone())
+            .await
+            .and_then(|img| img.push_and_group(&mut song.img_groups, &mut song.imgs));
+        if let Err(e) = res {
+            warn!("img was not added: {e}");
+        }
+    }
+
+    if song.imgs.is_empty() {
+        return Ok(Outcome::NotFound);
+    }
+    let img_id = song.img_groups.first_in_first_group();
+    song.selected_img = Some(img_id);
+    let src = song.imgs[img_id].src;
+    if !auto {
+        return Ok(Outcome::Picked(src));
+    }
+
+    let img = &mut song.imgs[img_id];
+    if let ImageProgress::Preview(urls) = &img.image {
+        let Some(first) = urls.first() else {
+            bail!("image has no url");
+        };
+        let format = ImgFormat::from_url(first);
+        let (bytes, url) = shared::get_img_from(client, urls.to_vec()).await?;
+        img.preview_to_decoded(bytes, format, url)?;
+    }
+    if !matches!(img.image, ImageProgress::Decoded(_)) {
+        bail!("image was not decoded");
+    }
+    let cover =
+        file_parser::apply_selected(song, &config.img_settings, &config.apply_settings, journal)?;
+    Ok(Outcome::Applied(src, cover.map(Box::new)))
+}
@@ -0,0 +1,117 @@
+mod apply;
+mod report;
+
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::{Error, anyhow, bail};
+
+const USAGE: &str = "\
+usage: mass_coverart [command]
+
+without a command the window is opened
+
+commands:
+  apply <path>... [options]   fetch covers and write the best match into every file,
+                              tracks of an album share the pick of the first one
+    -r, --recursive           walk sub folders
+    -a, --auto                write the first image of the top group,
+                              without it the picks are only printed
+    -n, --dry-run             with --auto, log the planned writes without touching files
+  report <path>... [options]  list files with missing, small, non-square, non jpg/png art
+                              or art that differs from the rest of the album
+    -r, --recursive           walk sub folders
+    -s, --min-size <px>       smaller art is low resolution, config value by default
+    -j, --json                print json instead of csv
+    -o, --output <file>       write the report into a file
+  help                        show this message";
+
+#[derive(Debug, Default)]
+pub struct ApplyArgs {
+    pub paths: Vec<PathBuf>,
+    pub recursive: bool,
+    pub auto: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ReportArgs {
+    pub paths: Vec<PathBuf>,
+    pub recursive: bool,
+    pub min_size: Option<u32>,
+    pub json: bool,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Apply(ApplyArgs),
+    Report(ReportArgs),
+    Help,
+}
+impl Command {
+    /// `None` if no command was given and the GUI should be launched
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, Error> {
+        let Some(cmd) = args.next() else {
+            return Ok(None);
+        };
+        match cmd.as_str() {
+            "apply" => {
+                let mut apply = ApplyArgs::default();
+                for arg in args {
+                    match arg.as_str() {
+                        "-r" | "--recursive" => apply.recursive = true,
+                        "-a" | "--auto" => apply.auto = true,
+                        "-n" | "--dry-run" => apply.dry_run = true,
+                        flag if flag.starts_with('-') => bail!("unknown option {flag}\n{USAGE}"),
+                        path => apply.paths.push(PathBuf::from(path)),
+                    }
+                }
+                if apply.paths.is_empty() {
+                    bail!("no path given\n{USAGE}");
+                }
+                Ok(Some(Self::Apply(apply)))
+            }
+            "report" => {
+                let mut report = ReportArgs::default();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-r" | "--recursive" => report.recursive = true,
+                        "-j" | "--json" => report.json = true,
+                        "-s" | "--min-size" => {
+                            let size = args.next().ok_or(anyhow!("{arg} needs a value"))?;
+                            report.min_size = Some(size.parse()?);
+                        }
+                        "-o" | "--output" => {
+                            let file = args.next().ok_or(anyhow!("{arg} needs a value"))?;
+                            report.output = Some(PathBuf::from(file));
+                        }
+                        flag if flag.starts_with('-') => bail!("unknown option {flag}\n{USAGE}"),
+                        path => report.paths.push(PathBuf::from(path)),
+                    }
+                }
+                if report.paths.is_empty() {
+                    bail!("no path given\n{USAGE}");
+                }
+                Ok(Some(Self::Report(report)))
+            }
+            "help" | "-h" | "--help" => Ok(Some(Self::Help)),
+            _ => bail!("unknown command {cmd}\n{USAGE}"),
+        }
+    }
+    pub fn run(self) -> Result<ExitCode, Error> {
+        match self {
+            Self::Apply(args) => {
+                let rt = tokio::runtime::Runtime::new()?;
+                rt.block_on(apply::apply(args))
+            }
+            Self::Report(args) => {
+                let rt = tokio::runtime::Runtime::new()?;
+                rt.block_on(report::report(args))
+            }
+            Self::Help => {
+                println!("{USAGE}");
+                Ok(ExitCode::SUCCESS)
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::process::ExitCode;
+
+use anyhow::Error;
+use rfd::FileHandle;
+
+use crate::{
+    app::config::Config,
+    cli::ReportArgs,
+    parser::{
+        file_parser::{ParseSettings, get_tags_data},
+        report,
+    },
+};
+
+pub async fn report(args: ReportArgs) -> Result<ExitCode, Error> {
+    let config = Config::load();
+    let parse_settings = ParseSettings {
+        recursive: args.recursive,
+        ..config.parse_settings.clone()
+    };
+    let min_size = args.min_size.unwrap_or(parse_settings.min_art_size);
+    let paths = args.paths.into_iter().map(FileHandle::from).collect();
+    let songs = get_tags_data(paths, parse_settings).await?;
+
+    let rows = report::classify(&songs, min_size);
+    let out = if args.json {
+        report::to_json(&rows)?
+    } else {
+        report::to_csv(&rows)
+    };
+    match &args.output {
+        Some(file) => tokio::fs::write(file, out).await?,
+        None => print!("{out}"),
+    }
+
+    // stdout may hold the report itself
+    let problems = rows.iter().filter(|r| !r.problems.is_empty()).count();
+    eprintln!("{} files: {problems} with problems", rows.len());
+    Ok(ExitCode::SUCCESS)
+}
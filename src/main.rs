@@ -2,10 +2,14 @@
 
 mod api;
 mod app;
+mod cli;
 mod parser;
 
+use std::{env, process::ExitCode};
+
 use app::iced_app::CoverUI;
-use flexi_logger::{Duplicate::Info, FileSpec, LogSpecification, Logger};
+use cli::Command;
+use flexi_logger::{Duplicate, FileSpec, LogSpecification, Logger};
 use iced::Size;
 pub type ImgHandle = iced::widget::image::Handle;
 pub type TaskHandle = iced::task::Handle;
@@ -16,14 +20,23 @@ pub type TaskHandle = iced::task::Handle;
 // make cross instead of remove
 // make plus/minus icons
 // remake top setting pannel
-fn main() -> Result<(), anyhow::Error> {
+fn main() -> Result<ExitCode, anyhow::Error> {
     #[cfg(debug_assertions)]
     unsafe {
-        use std::env;
-
         env::set_var("RUST_BACKTRACE", "full");
     }
 
+    let command = Command::parse(env::args().skip(1))?;
+    if command.is_some() {
+        attach_console();
+    }
+    // keep stdout for progress in headless mode
+    let duplicate = if command.is_some() {
+        Duplicate::Warn
+    } else {
+        Duplicate::Info
+    };
+
     let lvl = LogSpecification::info();
     Logger::with(lvl)
         .log_to_file(
@@ -31,10 +44,14 @@ fn main() -> Result<(), anyhow::Error> {
                 .basename("mass_coverart")
                 .use_timestamp(false),
         )
-        .duplicate_to_stdout(Info)
+        .duplicate_to_stdout(duplicate)
         .print_message()
         .start()?;
 
+    if let Some(command) = command {
+        return command.run();
+    }
+
     let init_size = (800.0, 600.0);
     iced::application(
         move || CoverUI::init(init_size),
@@ -45,7 +62,25 @@ fn main() -> Result<(), anyhow::Error> {
     .window_size(Size::new(init_size.0, init_size.1))
     .theme(CoverUI::theme)
     .subscription(CoverUI::subscription)
+    // closing goes through `Message::Exit` to save pending config
+    .exit_on_close_request(false)
     .centered()
     .run()?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Release builds are gui subsystem apps on windows and start without a console, print into the shell that ran them
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // fails when started from explorer, there is nothing to print into then
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
 }
+#[cfg(not(windows))]
+fn attach_console() {}
@@ -0,0 +1,158 @@
+//! Writes that never leave a truncated file behind: copy, edit the copy, rename it over
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, anyhow};
+use log::warn;
+
+/// Copy next to the original, keeps the extension so tag crates pick the right format
+fn temp_path(path: &Path) -> Result<PathBuf, Error> {
+    let stem = path
+        .file_stem()
+        .ok_or(anyhow!("{} is not a file", path.display()))?
+        .to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!(".{stem}.partial.{}", ext.to_string_lossy()),
+        None => format!(".{stem}.partial"),
+    };
+    Ok(path.with_file_name(name))
+}
+/// Copy left over by a write that was killed
+pub fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy())
+        .is_some_and(|n| n.starts_with('.') && n.contains(".partial"))
+}
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Run `write` on a copy of the audio file, check that the copy still reads, then replace the original
+/// * `backup`: keep the previous file as `<name>.bak`
+pub fn write_audio(
+    path: &Path,
+    backup: bool,
+    write: impl FnOnce(&Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let temp = temp_path(path)?;
+    let res = fs::copy(path, &temp)
+        .map_err(Error::from)
+        .and_then(|_| write(&temp))
+        .and_then(|_| {
+            audiotags::Tag::new()
+                .read_from_path(&temp)
+                .map(|_| ())
+                .map_err(|e| anyhow!("written file does not read back: {e}"))
+        })
+        .and_then(|_| replace(path, &temp, backup));
+    if res.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    res
+}
+/// Run `write` on a copy that is removed once `inspect` looked at it, the original is untouched
+pub fn try_audio<T>(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<(), Error>,
+    inspect: impl FnOnce(&Path) -> T,
+) -> Result<T, Error> {
+    let temp = temp_path(path)?;
+    let res = fs::copy(path, &temp)
+        .map_err(Error::from)
+        .and_then(|_| write(&temp))
+        .map(|_| inspect(&temp));
+    let _ = fs::remove_file(&temp);
+    res
+}
+/// Plain file written in full before it takes the place of the old one
+pub fn write_bytes(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let temp = temp_path(path)?;
+    let res = fs::write(&temp, bytes)
+        .map_err(Error::from)
+        .and_then(|_| replace(path, &temp, false));
+    if res.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    res
+}
+fn replace(path: &Path, temp: &Path, backup: bool) -> Result<(), Error> {
+    // data has to be on disk before the rename is, or a crash can leave an empty file
+    OpenOptions::new().write(true).open(temp)?.sync_all()?;
+    if backup && path.exists() {
+        let bak = backup_path(path);
+        if let Err(e) = fs::copy(path, &bak) {
+            warn!("backup {} was not written: {e}", bak.display());
+        }
+    }
+    fs::rename(temp, path)?;
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use anyhow::bail;
+    use id3::{TagLike, Version};
+
+    use crate::parser::atomic::{backup_path, is_temp, temp_path, write_audio, write_bytes};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mass_coverart_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn temp_keeps_extension() {
+        let temp = temp_path(&PathBuf::from("/music/01 song.mp3")).unwrap();
+        assert_eq!(temp, PathBuf::from("/music/.01 song.partial.mp3"));
+        assert!(is_temp(&temp));
+    }
+    #[test]
+    fn failed_write_keeps_original() {
+        let dir = temp_dir("atomic_fail");
+        let path = dir.join("song.mp3");
+        fs::write(&path, b"original").unwrap();
+        let res = write_audio(&path, true, |temp| {
+            fs::write(temp, b"trunc")?;
+            bail!("killed mid write")
+        });
+        let content = fs::read(&path).unwrap();
+        let left: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(res.is_err());
+        assert_eq!(content, b"original");
+        assert_eq!(left.len(), 1);
+    }
+    #[test]
+    fn write_with_backup() {
+        let dir = temp_dir("atomic_backup");
+        let path = dir.join("song.mp3");
+        fs::write(&path, b"").unwrap();
+        write_audio(&path, true, |temp| {
+            let mut tag = id3::Tag::new();
+            tag.set_title("title");
+            tag.write_to_path(temp, Version::Id3v24)?;
+            Ok(())
+        })
+        .unwrap();
+        let title = id3::Tag::read_from_path(&path)
+            .unwrap()
+            .title()
+            .map(str::to_string);
+        let bak = fs::read(backup_path(&path)).unwrap();
+        write_bytes(&dir.join("cover.jpg"), b"jpg").unwrap();
+        let cover = fs::read(dir.join("cover.jpg")).unwrap();
+        let count = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(title.as_deref(), Some("title"));
+        assert!(bak.is_empty());
+        assert_eq!(cover, b"jpg");
+        assert_eq!(count, 3);
+    }
+}
@@ -1,23 +1,36 @@
 use anyhow::{Error, bail};
 use iced::Length::Fill;
-use log::info;
+use log::{info, warn};
 use rfd::FileHandle;
+use serde::{Deserialize, Serialize};
 use std::{
+    ffi::OsStr,
     fmt::Debug,
     fs::{read, read_dir},
-    path::{Path, PathBuf},
+    io::Cursor,
+    path::{Component, Path, PathBuf},
     time::Instant,
 };
 
 use audiotags::{AudioTag, Picture};
+use image::{DynamicImage, ImageFormat};
 
-use crate::app::{
-    iced_app::CoverUI,
-    song::{OrigArt, Song, SongId},
-    tags::{Tag, TagType, Tags, USER_INPUT_TAG_SCORE},
+use crate::{
+    ImgHandle,
+    app::{
+        img::{ImageSettings, ImgFormat},
+        song::{OrigArt, Song},
+        tags::{Tag, TagType, Tags, USER_INPUT_TAG_SCORE},
+    },
+    parser::{
+        atomic,
+        journal::Journal,
+        provenance::{self, Provenance},
+    },
 };
+use bytes::Bytes;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum RegexType {
     Album,
     Title,
@@ -88,9 +101,70 @@ impl RegexType {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Where the chosen cover is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoverTarget {
+    Embed,
+    Folder,
+    Both,
+}
+impl CoverTarget {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Embed => "embed",
+            Self::Folder => "folder",
+            Self::Both => "both",
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Self::Embed => Self::Folder,
+            Self::Folder => Self::Both,
+            Self::Both => Self::Embed,
+        }
+    }
+}
+/// * `folder_file`: name of the image next to the tracks as typed, extension picks the format
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApplySettings {
+    pub target: CoverTarget,
+    pub folder_file: String,
+}
+impl Default for ApplySettings {
+    fn default() -> Self {
+        Self {
+            target: CoverTarget::Embed,
+            folder_file: DEFAULT_FOLDER_FILE.to_string(),
+        }
+    }
+}
+impl ApplySettings {
+    /// `folder_file` if it is a single jpeg or png file name, the default otherwise
+    pub fn folder_file(&self) -> &str {
+        let path = Path::new(&self.folder_file);
+        let mut parts = path.components();
+        let single = matches!(parts.next(), Some(Component::Normal(_))) && parts.next().is_none();
+        let ext = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_lowercase);
+        if single && ext.is_some_and(|ext| FOLDER_IMAGE_EXTS.contains(&ext.as_str())) {
+            return &self.folder_file;
+        }
+        warn!("folder file name {:?} is not used", self.folder_file);
+        DEFAULT_FOLDER_FILE
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+/// * `only_problems`: load only songs the library report finds a problem with
+/// * `min_art_size`: art with a smaller side is reported as low resolution
 pub struct ParseSettings {
     pub recursive: bool,
+    pub only_problems: bool,
+    pub min_art_size: u32,
     pub parse_file_name: bool,
     pub reg_keys: Vec<RegexType>,
     pub reg_separators: Vec<String>,
@@ -100,16 +174,29 @@ impl Default for ParseSettings {
     fn default() -> Self {
         Self {
             recursive: true,
+            only_problems: false,
+            min_art_size: 500,
             parse_file_name: false,
             reg_keys: vec![RegexType::Artist, RegexType::Title],
             reg_separators: vec![" - ".to_string()],
         }
     }
 }
+/// Names players look for next to the tracks, in order of preference
+const FOLDER_IMAGE_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+const FOLDER_IMAGE_EXTS: [&str; 3] = ["jpg", "jpeg", "png"];
+const DEFAULT_FOLDER_FILE: &str = "cover.jpg";
+
 pub type FileData = Box<dyn AudioTag + Send + Sync + 'static>;
+/// * `folder_images`: cover files found in the directory of the track
+/// * `provenance`: where the embedded cover was found, if this app applied it
+/// * `library`: folder the track was opened from, picks are learned per library
 pub struct TagData {
     pub path: PathBuf,
+    pub library: PathBuf,
     pub file: FileData,
+    pub folder_images: Vec<PathBuf>,
+    pub provenance: Option<Provenance>,
     pub artist: Option<String>,
     pub title: Option<String>,
     pub album: Option<String>,
@@ -127,8 +214,11 @@ impl Clone for TagData {
 impl TagData {
     fn new(path: PathBuf, file: FileData) -> Self {
         Self {
+            library: path.parent().map(Path::to_path_buf).unwrap_or_default(),
             path,
             file,
+            folder_images: Vec::new(),
+            provenance: None,
             artist: None,
             title: None,
             album: None,
@@ -141,8 +231,12 @@ pub async fn get_tags_data(
 ) -> Result<Vec<Song>, Error> {
     let mut ret = Vec::new();
     for path in path_vec {
-        let mut tags = parse_path(path.into(), set.recursive)?;
+        let path: PathBuf = path.into();
+        let mut tags = parse_path(path.clone(), set.recursive)?;
         for file in &mut tags {
+            if path.is_dir() {
+                file.tag_data.library = path.clone();
+            }
             parse_tags(file, &set);
         }
         ret.append(&mut tags);
@@ -157,11 +251,23 @@ fn map_tag(src: Option<&str>) -> Option<String> {
     }
 }
 
-pub fn parse_tags(song: &mut Song, set: &ParseSettings) {
-    let tags = &mut song.tag_data;
+fn read_tags(tags: &mut TagData) {
     tags.artist = map_tag(tags.file.artist());
     tags.title = map_tag(tags.file.title());
     tags.album = map_tag(tags.file.album_title());
+    tags.provenance = provenance::read(&tags.path).and_then(|v| Provenance::from_tag(&v));
+}
+/// Read the file again after it was restored on disk
+pub fn reload_file(song: &mut Song) -> Result<(), Error> {
+    let file = audiotags::Tag::new().read_from_path(&song.tag_data.path)?;
+    song.original_art = file.album_cover().map(|_| OrigArt::Unloaded);
+    song.tag_data.file = file;
+    read_tags(&mut song.tag_data);
+    Ok(())
+}
+pub fn parse_tags(song: &mut Song, set: &ParseSettings) {
+    let tags = &mut song.tag_data;
+    read_tags(tags);
     if !set.parse_file_name {
         return;
     }
@@ -190,20 +296,29 @@ pub fn parse_path(path: PathBuf, rec: bool) -> Result<Vec<Song>, Error> {
     let mut all_files = Vec::new();
     let p: &Path = path.as_ref();
     if p.is_file() {
-        let res = parse_file(path);
+        let images = p.parent().map(find_folder_images).unwrap_or_default();
+        let res = parse_file(path, &images);
         if let Ok(file) = res {
             all_files.push(file)
         }
     } else if p.is_dir() {
-        let all = read_dir(path)?;
-        for item in all {
-            let item = item?.path();
-            if !rec && item.is_dir() {
+        let items = read_dir(&path)?
+            .map(|item| item.map(|item| item.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // one listing of the folder serves every track in it
+        let images = folder_images(&items);
+        for item in items {
+            if atomic::is_temp(&item) {
                 continue;
             }
-            let res = parse_path(item, true);
-            if let Ok(mut files) = res {
-                all_files.append(&mut files);
+            if item.is_dir() {
+                if rec && let Ok(mut files) = parse_path(item, true) {
+                    all_files.append(&mut files);
+                }
+            } else if item.is_file()
+                && let Ok(file) = parse_file(item, &images)
+            {
+                all_files.push(file);
             }
         }
     } else if p.is_symlink() {
@@ -216,9 +331,37 @@ pub fn parse_path(path: PathBuf, rec: bool) -> Result<Vec<Song>, Error> {
     }
     Ok(all_files)
 }
-pub fn parse_file(path: PathBuf) -> Result<Song, Error> {
+/// * `folder_images`: cover files of the track's directory, see `find_folder_images`
+pub fn parse_file(path: PathBuf, folder_images: &[PathBuf]) -> Result<Song, Error> {
     let file = audiotags::Tag::new().read_from_path(&path)?;
-    Ok(Song::new(TagData::new(path, file)))
+    let mut tag_data = TagData::new(path, file);
+    tag_data.folder_images = folder_images.to_vec();
+    Ok(Song::new(tag_data))
+}
+/// Existing cover files of a directory, case insensitive
+pub fn find_folder_images(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else {
+        return Vec::new();
+    };
+    let paths: Vec<_> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    folder_images(&paths)
+}
+/// Cover files among the entries of one directory, best name first
+fn folder_images(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut found: Vec<(usize, PathBuf)> = paths
+        .iter()
+        .filter_map(|p| {
+            let stem = p.file_stem()?.to_string_lossy().to_lowercase();
+            let ext = p.extension()?.to_string_lossy().to_lowercase();
+            if !FOLDER_IMAGE_EXTS.contains(&ext.as_str()) {
+                return None;
+            }
+            let rank = FOLDER_IMAGE_NAMES.iter().position(|n| *n == stem)?;
+            p.is_file().then(|| (rank, p.clone()))
+        })
+        .collect();
+    found.sort();
+    found.into_iter().map(|(_, p)| p).collect()
 }
 pub fn is_rtl(s: &str) -> bool {
     let cs = s.chars();
@@ -230,25 +373,104 @@ pub fn is_rtl(s: &str) -> bool {
     }
     false
 }
-pub fn apply_selected(ui: &mut CoverUI, id: SongId) -> Result<(), Error> {
-    let song = &mut ui.state.songs[id];
-
-    if let Some(img_id) = song.selected_img {
-        let img = &mut song.imgs[img_id];
-        info!("final img {}", img.image.dbg());
-        let (fin, fin_type, fin_prev) = img.final_img(&ui.state.img_settings);
-        song.original_art = Some(OrigArt::Loaded(fin_prev));
+/// Final image of the selected art with its preview, shared by every track of an album
+/// * `folders`: directories that got the folder file, tracks of an album often share one
+pub struct Cover {
+    pub bytes: Bytes,
+    pub format: ImgFormat,
+    pub preview: ImgHandle,
+    pub provenance: Provenance,
+    pub folders: Vec<PathBuf>,
+}
 
+/// Write the selected image and return it for the other tracks of the album
+pub fn apply_selected(
+    song: &mut Song,
+    set: &ImageSettings,
+    apply: &ApplySettings,
+    journal: &mut Journal,
+) -> Result<Option<Cover>, Error> {
+    let Some(img_id) = song.selected_img else {
+        return Ok(None);
+    };
+    let img = &mut song.imgs[img_id];
+    info!("final img {}", img.image.dbg());
+    let provenance = Provenance::new(img);
+    let (bytes, format, preview) = img.final_img(set)?;
+    let mut cover = Cover {
+        bytes,
+        format,
+        preview,
+        provenance,
+        folders: Vec::new(),
+    };
+    write_cover(song, &mut cover, apply, journal)?;
+    Ok(Some(cover))
+}
+pub fn write_cover(
+    song: &mut Song,
+    cover: &mut Cover,
+    apply: &ApplySettings,
+    journal: &mut Journal,
+) -> Result<(), Error> {
+    if apply.target != CoverTarget::Folder {
         let pic = Picture {
-            data: &fin,
-            mime_type: fin_type.audiotags(),
+            data: &cover.bytes,
+            mime_type: cover.format.audiotags(),
         };
-        let tags = &mut song.tag_data.file;
-        tags.set_album_cover(pic);
-        tags.write_to_path(song.tag_data.path.to_str().unwrap())?;
+        let tag_data = &mut song.tag_data;
+        tag_data.file.set_album_cover(pic);
+        // provenance goes into the same copy, a track is rewritten once per cover
+        let value = cover.provenance.to_tag();
+        journal.write_tags_with(&tag_data.path, &mut tag_data.file, "embed cover", |temp| {
+            provenance::write(temp, Some(&value))
+        })?;
+        // a dry run leaves the song showing what is on disk
+        if !journal.dry_run {
+            tag_data.provenance = Some(cover.provenance.clone());
+            song.original_art = Some(OrigArt::Loaded(cover.preview.clone()));
+        }
+    }
+    let dir = song.tag_data.path.parent().expect("file has root");
+    if apply.target != CoverTarget::Embed && !cover.folders.iter().any(|d| d == dir) {
+        write_folder_image(
+            dir,
+            apply.folder_file(),
+            &cover.bytes,
+            cover.format,
+            journal,
+        )?;
+        cover.folders.push(dir.to_path_buf());
     }
     Ok(())
 }
+/// Save the cover next to the tracks, re-encoded when the name asks for another format
+/// * `name`: file name with an image extension
+fn write_folder_image(
+    dir: &Path,
+    name: &str,
+    fin: &Bytes,
+    fin_type: ImgFormat,
+    journal: &mut Journal,
+) -> Result<PathBuf, Error> {
+    let path = dir.join(name);
+    let format = ImageFormat::from_path(&path)?;
+    if format == fin_type.imageio() {
+        journal.write_file(&path, fin, "folder image")?;
+    } else {
+        let img = image::load_from_memory(fin)?;
+        // jpeg has no alpha channel
+        let img = if format == ImageFormat::Jpeg {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        } else {
+            img
+        };
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), format)?;
+        journal.write_file(&path, &bytes, "folder image")?;
+    }
+    Ok(path)
+}
 pub fn find_edited_tags(tag_data: &TagData) -> Vec<Tag> {
     let title = map_tag(tag_data.file.title());
     let artist = map_tag(tag_data.file.artist());
@@ -278,3 +500,92 @@ pub fn find_edited_tags(tag_data: &TagData) -> Vec<Tag> {
     }
     tags
 }
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor, path::PathBuf};
+
+    use bytes::Bytes;
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+
+    use crate::{
+        app::img::ImgFormat,
+        parser::{
+            file_parser::{ApplySettings, find_folder_images, write_folder_image},
+            journal::Journal,
+        },
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mass_coverart_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn folder_images_in_order() {
+        let dir = temp_dir("folder_images");
+        for name in [
+            "Folder.JPG",
+            "cover.png",
+            "back.jpg",
+            "cover.txt",
+            "song.mp3",
+        ] {
+            fs::write(dir.join(name), []).unwrap();
+        }
+        let found = find_folder_images(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = found
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["cover.png", "Folder.JPG"]);
+    }
+    #[test]
+    fn folder_file_checked() {
+        let name = |folder_file: &str| {
+            let set = ApplySettings {
+                folder_file: folder_file.to_string(),
+                ..Default::default()
+            };
+            set.folder_file().to_string()
+        };
+        assert_eq!(name("Folder.PNG"), "Folder.PNG");
+        assert_eq!(name("front.jpeg"), "front.jpeg");
+        for bad in [
+            "",
+            "cover",
+            "cover.txt",
+            "../cover.jpg",
+            "art/cover.jpg",
+            "/cover.jpg",
+        ] {
+            assert_eq!(name(bad), "cover.jpg", "{bad}");
+        }
+    }
+    #[test]
+    fn folder_image_format() {
+        let dir = temp_dir("folder_write");
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(4, 4))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let png = Bytes::from_owner(png);
+
+        let mut journal = Journal::default();
+        let kept =
+            write_folder_image(&dir, "folder.png", &png, ImgFormat::Png, &mut journal).unwrap();
+        let converted =
+            write_folder_image(&dir, "cover.jpg", &png, ImgFormat::Png, &mut journal).unwrap();
+        let kept_bytes = fs::read(&kept).unwrap();
+        let converted_bytes = fs::read(&converted).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(kept.file_name().unwrap(), "folder.png");
+        assert_eq!(kept_bytes, png.to_vec());
+        assert_eq!(
+            image::guess_format(&converted_bytes).unwrap(),
+            ImageFormat::Jpeg
+        );
+    }
+}
@@ -0,0 +1,489 @@
+//! Previous state of every written file, backs undo and dry runs
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error;
+use audiotags::{MimeType, Picture};
+use log::{info, warn};
+
+use crate::parser::{atomic, file_parser::FileData, provenance, release_tags::SavedIds};
+
+/// Values `audiotags` can restore, the cover provenance and the musicbrainz ids of `release_tags`
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TagSnapshot {
+    provenance: Option<String>,
+    ids: SavedIds,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    year: Option<i32>,
+    track: Option<u16>,
+    total_tracks: Option<u16>,
+    disc: Option<u16>,
+    total_discs: Option<u16>,
+    cover: Option<(Vec<u8>, MimeType)>,
+}
+impl TagSnapshot {
+    /// Tags as they are on disk, the loaded `FileData` may already be edited
+    fn read(path: &Path) -> Self {
+        let extra = Self {
+            provenance: provenance::read(path),
+            ids: SavedIds::read(path),
+            ..Default::default()
+        };
+        let file = match audiotags::Tag::new().read_from_path(path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!(
+                    "{} has no readable tags, undo clears them: {e}",
+                    path.display()
+                );
+                return extra;
+            }
+        };
+        let owned = |s: Option<&str>| s.map(str::to_string);
+        Self {
+            title: owned(file.title()),
+            artist: owned(file.artist()),
+            album: owned(file.album_title()),
+            album_artist: owned(file.album_artist()),
+            year: file.year(),
+            track: file.track_number(),
+            total_tracks: file.total_tracks(),
+            disc: file.disc_number(),
+            total_discs: file.total_discs(),
+            cover: file
+                .album_cover()
+                .map(|pic| (pic.data.to_vec(), pic.mime_type)),
+            ..extra
+        }
+    }
+    /// Changed values as `name: old -> new` lines
+    fn diff(&self, new: &Self) -> Vec<String> {
+        let text = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+        let num = |v: Option<u16>| v.map_or("-".to_string(), |v| v.to_string());
+        let cover = |v: &Option<(Vec<u8>, MimeType)>| match v {
+            Some((data, mime)) => format!("{mime:?} {} KB", data.len() / 1024),
+            None => "-".to_string(),
+        };
+        let ids = |v: &SavedIds| v.summary();
+        let lines = [
+            (
+                "title",
+                self.title != new.title,
+                text(&self.title),
+                text(&new.title),
+            ),
+            (
+                "artist",
+                self.artist != new.artist,
+                text(&self.artist),
+                text(&new.artist),
+            ),
+            (
+                "album",
+                self.album != new.album,
+                text(&self.album),
+                text(&new.album),
+            ),
+            (
+                "album artist",
+                self.album_artist != new.album_artist,
+                text(&self.album_artist),
+                text(&new.album_artist),
+            ),
+            (
+                "year",
+                self.year != new.year,
+                self.year.map_or("-".to_string(), |v| v.to_string()),
+                new.year.map_or("-".to_string(), |v| v.to_string()),
+            ),
+            (
+                "track",
+                self.track != new.track,
+                num(self.track),
+                num(new.track),
+            ),
+            (
+                "total tracks",
+                self.total_tracks != new.total_tracks,
+                num(self.total_tracks),
+                num(new.total_tracks),
+            ),
+            ("disc", self.disc != new.disc, num(self.disc), num(new.disc)),
+            (
+                "total discs",
+                self.total_discs != new.total_discs,
+                num(self.total_discs),
+                num(new.total_discs),
+            ),
+            (
+                "cover",
+                self.cover != new.cover,
+                cover(&self.cover),
+                cover(&new.cover),
+            ),
+            (
+                "provenance",
+                self.provenance != new.provenance,
+                text(&self.provenance),
+                text(&new.provenance),
+            ),
+            (
+                "musicbrainz ids",
+                self.ids != new.ids,
+                ids(&self.ids),
+                ids(&new.ids),
+            ),
+        ];
+        lines
+            .into_iter()
+            .filter(|(_, changed, _, _)| *changed)
+            .map(|(name, _, old, new)| format!("{name}: {old} -> {new}"))
+            .collect()
+    }
+    fn restore(&self, path: &Path) -> Result<(), Error> {
+        let mut file = audiotags::Tag::new().read_from_path(path)?;
+        match &self.title {
+            Some(v) => file.set_title(v),
+            None => file.remove_title(),
+        }
+        match &self.artist {
+            Some(v) => file.set_artist(v),
+            None => file.remove_artist(),
+        }
+        match &self.album {
+            Some(v) => file.set_album_title(v),
+            None => file.remove_album_title(),
+        }
+        match &self.album_artist {
+            Some(v) => file.set_album_artist(v),
+            None => file.remove_album_artist(),
+        }
+        match self.year {
+            Some(v) => file.set_year(v),
+            None => file.remove_year(),
+        }
+        match self.track {
+            Some(v) => file.set_track_number(v),
+            None => file.remove_track_number(),
+        }
+        match self.total_tracks {
+            Some(v) => file.set_total_tracks(v),
+            None => file.remove_total_tracks(),
+        }
+        match self.disc {
+            Some(v) => file.set_disc_number(v),
+            None => file.remove_disc_number(),
+        }
+        match self.total_discs {
+            Some(v) => file.set_total_discs(v),
+            None => file.remove_total_discs(),
+        }
+        match &self.cover {
+            Some((data, mime_type)) => file.set_album_cover(Picture {
+                data,
+                mime_type: *mime_type,
+            }),
+            None => file.remove_album_cover(),
+        }
+        atomic::write_audio(path, false, |temp| {
+            file.write_to_path(temp.to_str().unwrap())?;
+            provenance::write(temp, self.provenance.as_deref())?;
+            self.ids.restore(temp)
+        })
+    }
+}
+
+/// * `File`: content of a file written next to the tracks, `None` if it did not exist
+#[derive(Clone, Debug)]
+enum Entry {
+    Tags(PathBuf, TagSnapshot),
+    File(PathBuf, Option<Vec<u8>>),
+}
+impl Entry {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Tags(path, _) | Self::File(path, _) => path,
+        }
+    }
+    /// `.bak` still holds the original, restoring does not replace it
+    fn undo(&self) -> Result<(), Error> {
+        match self {
+            Self::Tags(path, snapshot) => snapshot.restore(path),
+            Self::File(path, Some(bytes)) => atomic::write_bytes(path, bytes),
+            Self::File(path, None) => Ok(fs::remove_file(path)?),
+        }
+    }
+}
+
+/// Writes grouped by user action, newest last
+/// * `dry_run`: only log the values that would change, loaded tags are left as on disk
+/// * `keep_backup`: leave `<name>.bak` next to every rewritten track
+#[derive(Default)]
+pub struct Journal {
+    batches: Vec<Vec<Entry>>,
+    pub dry_run: bool,
+    pub keep_backup: bool,
+}
+impl Journal {
+    pub fn new(dry_run: bool, keep_backup: bool) -> Self {
+        Self {
+            batches: Vec::new(),
+            dry_run,
+            keep_backup,
+        }
+    }
+    /// Writes until the next `begin` are undone together
+    pub fn begin(&mut self) {
+        if self.batches.last().is_none_or(|b| !b.is_empty()) {
+            self.batches.push(Vec::new());
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        self.batches.iter().any(|b| !b.is_empty())
+    }
+    /// Keep the first state of a file in the current batch, true if this is the first write
+    fn record(&mut self, path: &Path, entry: impl FnOnce() -> Entry) -> bool {
+        if self.batches.is_empty() {
+            self.batches.push(Vec::new());
+        }
+        let batch = self.batches.last_mut().expect("batch was started");
+        let first = !batch.iter().any(|e| e.path() == path);
+        if first {
+            batch.push(entry());
+        }
+        first
+    }
+    /// Snapshot the tags on disk, then write the edited `file` over them
+    pub fn write_tags(
+        &mut self,
+        path: &Path,
+        file: &mut FileData,
+        change: &str,
+    ) -> Result<(), Error> {
+        self.write_tags_with(path, file, change, |_| Ok(()))
+    }
+    /// Like `write_tags`, `extra` adds tags `audiotags` does not know to the same temp copy
+    pub fn write_tags_with(
+        &mut self,
+        path: &Path,
+        file: &mut FileData,
+        change: &str,
+        extra: impl FnOnce(&Path) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let res = self.write_extra(path, change, |temp| {
+            file.write_to_path(temp.to_str().unwrap())?;
+            extra(temp)
+        });
+        if self.dry_run {
+            // callers edit the loaded tags to plan the write
+            *file = audiotags::Tag::new().read_from_path(path)?;
+        }
+        res
+    }
+    /// Snapshot the tags on disk, then let `write` edit a temp copy of the track
+    pub fn write_extra(
+        &mut self,
+        path: &Path,
+        change: &str,
+        write: impl FnOnce(&Path) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.dry_run {
+            let planned = atomic::try_audio(path, write, TagSnapshot::read)?;
+            let changes = TagSnapshot::read(path).diff(&planned);
+            info!(
+                "dry run, {change}: {}\n{}",
+                path.display(),
+                changes.join("\n")
+            );
+            return Ok(());
+        }
+        let first = self.record(path, || {
+            Entry::Tags(path.to_path_buf(), TagSnapshot::read(path))
+        });
+        // later writes of the batch would back up a half applied file
+        atomic::write_audio(path, self.keep_backup && first, write)
+    }
+    pub fn write_file(&mut self, path: &Path, bytes: &[u8], change: &str) -> Result<(), Error> {
+        if self.dry_run {
+            let old = match fs::metadata(path) {
+                Ok(meta) => format!("{} KB", meta.len() / 1024),
+                Err(_) => "-".to_string(),
+            };
+            info!(
+                "dry run, {change}: {}\n{old} -> {} KB",
+                path.display(),
+                bytes.len() / 1024
+            );
+            return Ok(());
+        }
+        // an unreadable file is not one that did not exist, undo would delete it
+        let old = match fs::read(path) {
+            Ok(old) => Some(old),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.record(path, || Entry::File(path.to_path_buf(), old));
+        atomic::write_bytes(path, bytes)
+    }
+    /// Restore files of the newest batch, returns the restored paths
+    pub fn undo_last(&mut self) -> Vec<PathBuf> {
+        while let Some(batch) = self.batches.pop() {
+            if !batch.is_empty() {
+                return Self::undo_batch(batch);
+            }
+        }
+        Vec::new()
+    }
+    /// Restore every file written since start
+    pub fn undo_session(&mut self) -> Vec<PathBuf> {
+        let mut restored = Vec::new();
+        while self.can_undo() {
+            restored.extend(self.undo_last());
+        }
+        restored
+    }
+    fn undo_batch(batch: Vec<Entry>) -> Vec<PathBuf> {
+        let mut restored = Vec::new();
+        for entry in batch.into_iter().rev() {
+            match entry.undo() {
+                Ok(()) => {
+                    info!("restored {}", entry.path().display());
+                    restored.push(entry.path().to_path_buf());
+                }
+                Err(e) => warn!("{} was not restored: {e}", entry.path().display()),
+            }
+        }
+        restored
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use id3::{TagLike, Version};
+
+    use crate::parser::{
+        atomic::backup_path,
+        journal::{Journal, TagSnapshot},
+    };
+
+    fn set_title(path: &Path, title: &str) -> anyhow::Result<()> {
+        let mut tag = id3::Tag::read_from_path(path)?;
+        tag.set_title(title);
+        tag.write_to_path(path, Version::Id3v24)?;
+        Ok(())
+    }
+    fn title(path: &Path) -> Option<String> {
+        let tag = id3::Tag::read_from_path(path).ok()?;
+        tag.title().map(str::to_string)
+    }
+
+    #[test]
+    fn undo_files() {
+        let dir = env::temp_dir().join(format!("mass_coverart_journal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("cover.jpg");
+        let new = dir.join("folder.jpg");
+        fs::write(&old, b"old").unwrap();
+
+        let mut journal = Journal::default();
+        journal.begin();
+        journal.write_file(&old, b"first", "cover").unwrap();
+        // second write of a batch keeps the first snapshot
+        journal.write_file(&old, b"second", "cover").unwrap();
+        journal.begin();
+        journal.write_file(&new, b"new", "cover").unwrap();
+
+        assert_eq!(journal.undo_last(), vec![new.clone()]);
+        assert!(!new.exists());
+        assert_eq!(fs::read(&old).unwrap(), b"second");
+        journal.write_file(&new, b"new", "cover").unwrap();
+        assert_eq!(journal.undo_session().len(), 2);
+        assert!(!journal.can_undo());
+        assert_eq!(fs::read(&old).unwrap(), b"old");
+
+        let mut dry = Journal::new(true, false);
+        dry.write_file(&old, b"dry", "cover").unwrap();
+        let content = fs::read(&old).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(content, b"old");
+        assert!(!dry.can_undo());
+    }
+    #[test]
+    fn dry_run_planned() {
+        let dir = env::temp_dir().join(format!("mass_coverart_journal_dry_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.mp3");
+        fs::write(&path, b"").unwrap();
+        id3::Tag::new()
+            .write_to_path(&path, Version::Id3v24)
+            .unwrap();
+        set_title(&path, "original").unwrap();
+
+        let mut dry = Journal::new(true, true);
+        dry.write_extra(&path, "tags", |temp| set_title(temp, "new"))
+            .unwrap();
+        let kept = title(&path);
+        let count = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(kept.as_deref(), Some("original"));
+        assert_eq!(count, 1);
+        assert!(!dry.can_undo());
+
+        let old = TagSnapshot {
+            title: Some("original".to_string()),
+            year: Some(1999),
+            ..Default::default()
+        };
+        let new = TagSnapshot {
+            title: Some("new".to_string()),
+            year: Some(1999),
+            track: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            old.diff(&new),
+            vec!["title: original -> new", "track: - -> 3"]
+        );
+    }
+    #[test]
+    fn backup_once_per_batch() {
+        let dir = env::temp_dir().join(format!("mass_coverart_journal_bak_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.mp3");
+        fs::write(&path, b"").unwrap();
+        id3::Tag::new()
+            .write_to_path(&path, Version::Id3v24)
+            .unwrap();
+        set_title(&path, "original").unwrap();
+
+        let mut journal = Journal::new(false, true);
+        journal.begin();
+        journal
+            .write_extra(&path, "tags", |temp| set_title(temp, "tags"))
+            .unwrap();
+        journal
+            .write_extra(&path, "ids", |temp| set_title(temp, "ids"))
+            .unwrap();
+        let after_first = title(&backup_path(&path));
+        journal.begin();
+        journal
+            .write_extra(&path, "again", |temp| set_title(temp, "again"))
+            .unwrap();
+        let after_second = title(&backup_path(&path));
+        journal.undo_session();
+        let bak = title(&backup_path(&path));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(after_first.as_deref(), Some("original"));
+        assert_eq!(after_second.as_deref(), Some("ids"));
+        // undo leaves the backup alone
+        assert_eq!(bak.as_deref(), Some("ids"));
+    }
+}
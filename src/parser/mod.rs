@@ -1 +1,6 @@
+pub mod atomic;
 pub mod file_parser;
+pub mod journal;
+pub mod provenance;
+pub mod release_tags;
+pub mod report;
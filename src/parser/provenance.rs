@@ -0,0 +1,149 @@
+//! Where the embedded cover came from, kept in a custom tag next to it
+use std::{iter, path::Path};
+
+use anyhow::{Error, bail};
+use chrono::Local;
+use id3::{ErrorKind, TagLike, Version, frame::ExtendedText};
+use log::warn;
+use mp4ameta::{Data, FreeformIdent};
+use serde::{Deserialize, Serialize};
+
+use crate::app::img::SongImg;
+
+/// id3 TXXX description, vorbis comment and mp4 freeform name
+const KEY: &str = "COVERART_PROVENANCE";
+const MP4_MEAN: &str = "com.apple.iTunes";
+
+/// * `url`: full size image that was downloaded, `None` for local and raw images
+/// * `resolution`: of the image before downscale and crop
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: String,
+    pub url: Option<String>,
+    pub resolution: Option<(u32, u32)>,
+    pub date: String,
+}
+impl Provenance {
+    pub fn new(img: &SongImg) -> Self {
+        Self {
+            source: img.src.to_string(),
+            url: img.url.clone(),
+            resolution: img.orig_res,
+            date: Local::now().format("%Y-%m-%d").to_string(),
+        }
+    }
+    pub fn to_tag(&self) -> String {
+        serde_json::to_string(self).expect("provenance serializes")
+    }
+    pub fn from_tag(value: &str) -> Option<Self> {
+        serde_json::from_str(value)
+            .inspect_err(|e| warn!("cover provenance tag was not parsed: {e}"))
+            .ok()
+    }
+    /// Lines shown next to the original art
+    pub fn summary(&self) -> String {
+        let mut lines = format!("from: {}\napplied: {}", self.source, self.date);
+        if let Some((w, h)) = self.resolution {
+            lines.push_str(&format!("\noriginal: {w}x{h}"));
+        }
+        if let Some(url) = &self.url {
+            lines.push_str(&format!("\nurl: {url}"));
+        }
+        lines
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+/// Raw tag value, `None` if the file has none or cannot be read
+pub fn read(path: &Path) -> Option<String> {
+    match extension(path).as_str() {
+        "mp3" => id3::Tag::read_from_path(path)
+            .ok()?
+            .extended_texts()
+            .find(|t| t.description == KEY)
+            .map(|t| t.value.clone()),
+        "flac" => metaflac::Tag::read_from_path(path)
+            .ok()?
+            .get_vorbis(KEY)?
+            .next()
+            .map(str::to_string),
+        "m4a" | "m4b" | "mp4" => mp4ameta::Tag::read_from_path(path)
+            .ok()?
+            .strings_of(&FreeformIdent::new(MP4_MEAN, KEY))
+            .next()
+            .map(str::to_string),
+        _ => None,
+    }
+}
+/// Replace the tag value, `None` removes it
+pub fn write(path: &Path, value: Option<&str>) -> Result<(), Error> {
+    match extension(path).as_str() {
+        "mp3" => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => bail!(e),
+            };
+            tag.remove_extended_text(Some(KEY), None);
+            if let Some(value) = value {
+                tag.add_frame(ExtendedText {
+                    description: KEY.to_string(),
+                    value: value.to_string(),
+                });
+            }
+            tag.write_to_path(path, Version::Id3v24)?;
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path)?;
+            match value {
+                Some(value) => tag.set_vorbis(KEY, vec![value]),
+                None => tag.remove_vorbis(KEY),
+            }
+            tag.save()?;
+        }
+        "m4a" | "m4b" | "mp4" => {
+            let mut tag = mp4ameta::Tag::read_from_path(path)?;
+            let ident = FreeformIdent::new(MP4_MEAN, KEY);
+            match value {
+                Some(value) => tag.set_all_data(ident, iter::once(Data::Utf8(value.to_string()))),
+                None => tag.remove_data_of(&ident),
+            }
+            tag.write_to_path(path)?;
+        }
+        ext => warn!("cover provenance is not supported for .{ext} files"),
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::parser::provenance::{Provenance, read, write};
+
+    #[test]
+    fn id3_round_trip() {
+        let path = env::temp_dir().join(format!(
+            "mass_coverart_provenance_{}.mp3",
+            std::process::id()
+        ));
+        fs::write(&path, []).unwrap();
+        let provenance = Provenance {
+            source: "deezer.com (%artist% %album%)".to_string(),
+            url: Some("https://cdn/1800x1800-000000-80-0-0.jpg".to_string()),
+            resolution: Some((1800, 1800)),
+            date: "2026-10-17".to_string(),
+        };
+        write(&path, Some(&provenance.to_tag())).unwrap();
+        let value = read(&path);
+        write(&path, None).unwrap();
+        let removed = read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Provenance::from_tag(&value.unwrap()), Some(provenance));
+        assert_eq!(removed, None);
+    }
+}
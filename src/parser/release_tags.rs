@@ -0,0 +1,337 @@
+//! Release metadata that `audiotags` has no setters for, written with the format crates
+use std::path::Path;
+
+use anyhow::{Error, bail};
+use id3::{
+    ErrorKind, TagLike, Version,
+    frame::{ExtendedText, UniqueFileIdentifier},
+};
+use log::{info, warn};
+use mp4ameta::{Data, FreeformIdent};
+use serde::{Deserialize, Serialize};
+
+use crate::parser::{file_parser::TagData, journal::Journal};
+
+/// Owner of the recording id in an id3 UFID frame
+const UFID_OWNER: &str = "http://musicbrainz.org";
+const MP4_MEAN: &str = "com.apple.iTunes";
+
+/// Release an image was found on, carried next to `SongImg::feedback`
+/// * `recording_id`, `artist_ids`, `track`: only known when found through the recording
+/// * `track`, `disc`: number and total
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseInfo {
+    pub release_id: String,
+    pub release_group_id: Option<String>,
+    pub recording_id: Option<String>,
+    pub artist_ids: Vec<String>,
+    pub album_artist_ids: Vec<String>,
+    pub album_artist: Option<String>,
+    pub year: Option<i32>,
+    pub track: Option<(u16, u16)>,
+    pub disc: Option<(u16, u16)>,
+}
+
+/// Identifier with its names in Picard's tag mapping
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MbId {
+    Release,
+    ReleaseGroup,
+    Recording,
+    Artist,
+    AlbumArtist,
+}
+impl MbId {
+    const ALL: [Self; 5] = [
+        Self::Release,
+        Self::ReleaseGroup,
+        Self::Recording,
+        Self::Artist,
+        Self::AlbumArtist,
+    ];
+    /// id3 TXXX description and mp4 freeform name, id3 keeps the recording in UFID
+    fn description(self) -> &'static str {
+        match self {
+            Self::Release => "MusicBrainz Album Id",
+            Self::ReleaseGroup => "MusicBrainz Release Group Id",
+            Self::Recording => "MusicBrainz Track Id",
+            Self::Artist => "MusicBrainz Artist Id",
+            Self::AlbumArtist => "MusicBrainz Album Artist Id",
+        }
+    }
+    fn vorbis_key(self) -> &'static str {
+        match self {
+            Self::Release => "MUSICBRAINZ_ALBUMID",
+            Self::ReleaseGroup => "MUSICBRAINZ_RELEASEGROUPID",
+            Self::Recording => "MUSICBRAINZ_TRACKID",
+            Self::Artist => "MUSICBRAINZ_ARTISTID",
+            Self::AlbumArtist => "MUSICBRAINZ_ALBUMARTISTID",
+        }
+    }
+}
+
+impl ReleaseInfo {
+    fn ids(&self) -> Vec<(MbId, Vec<String>)> {
+        let mut ids = vec![(MbId::Release, vec![self.release_id.clone()])];
+        if let Some(id) = &self.release_group_id {
+            ids.push((MbId::ReleaseGroup, vec![id.clone()]));
+        }
+        if let Some(id) = &self.recording_id {
+            ids.push((MbId::Recording, vec![id.clone()]));
+        }
+        if !self.artist_ids.is_empty() {
+            ids.push((MbId::Artist, self.artist_ids.clone()));
+        }
+        if !self.album_artist_ids.is_empty() {
+            ids.push((MbId::AlbumArtist, self.album_artist_ids.clone()));
+        }
+        ids
+    }
+}
+
+/// Write year, album artist, track and disc numbers, then the musicbrainz ids
+pub fn write_release(
+    tag_data: &mut TagData,
+    release: &ReleaseInfo,
+    journal: &mut Journal,
+) -> Result<(), Error> {
+    let file = &mut tag_data.file;
+    if let Some(year) = release.year {
+        file.set_year(year);
+    }
+    if let Some(artist) = &release.album_artist {
+        file.set_album_artist(artist);
+    }
+    if let Some((number, total)) = release.track {
+        file.set_track_number(number);
+        file.set_total_tracks(total);
+    }
+    if let Some((number, total)) = release.disc {
+        file.set_disc_number(number);
+        file.set_total_discs(total);
+    }
+    journal.write_tags(&tag_data.path, file, "release tags")?;
+    journal.write_extra(&tag_data.path, "musicbrainz ids", |temp| {
+        write_ids(temp, &release.ids())
+    })?;
+    info!(
+        "release {} written to {}",
+        release.release_id,
+        tag_data.path.display()
+    );
+    Ok(())
+}
+
+/// Musicbrainz ids as they are on disk, the journal keeps them to undo `write_release`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SavedIds(Vec<(MbId, Vec<String>)>);
+impl SavedIds {
+    pub fn read(path: &Path) -> Self {
+        let ids = match extension(path).as_str() {
+            "mp3" => read_id3(path),
+            "flac" => read_vorbis(path),
+            "m4a" | "m4b" | "mp4" => read_mp4(path),
+            _ => None,
+        };
+        Self(ids.unwrap_or_default())
+    }
+    /// Remove every id, then write back the saved ones
+    pub fn restore(&self, path: &Path) -> Result<(), Error> {
+        match extension(path).as_str() {
+            "mp3" => clear_id3(path)?,
+            "flac" => clear_vorbis(path)?,
+            "m4a" | "m4b" | "mp4" => clear_mp4(path)?,
+            _ => return Ok(()),
+        }
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        write_ids(path, &self.0)
+    }
+    pub fn summary(&self) -> String {
+        let ids: Vec<_> = self
+            .0
+            .iter()
+            .map(|(key, values)| format!("{}={}", key.vorbis_key(), values.join(";")))
+            .collect();
+        ids.join(", ")
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+fn write_ids(path: &Path, ids: &[(MbId, Vec<String>)]) -> Result<(), Error> {
+    let ext = extension(path);
+    match ext.as_str() {
+        "mp3" => write_id3(path, ids),
+        "flac" => write_vorbis(path, ids),
+        "m4a" | "m4b" | "mp4" => write_mp4(path, ids),
+        _ => {
+            warn!("musicbrainz ids are not supported for .{ext} files");
+            Ok(())
+        }
+    }
+}
+fn write_id3(path: &Path, ids: &[(MbId, Vec<String>)]) -> Result<(), Error> {
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => id3::Tag::new(),
+        Err(e) => bail!(e),
+    };
+    for (key, values) in ids {
+        if *key == MbId::Recording {
+            tag.add_frame(UniqueFileIdentifier {
+                owner_identifier: UFID_OWNER.to_string(),
+                identifier: values[0].as_bytes().to_vec(),
+            });
+            continue;
+        }
+        // id3v2.4 separates multiple values with null
+        tag.add_frame(ExtendedText {
+            description: key.description().to_string(),
+            value: values.join("\0"),
+        });
+    }
+    tag.write_to_path(path, Version::Id3v24)?;
+    Ok(())
+}
+fn read_id3(path: &Path) -> Option<Vec<(MbId, Vec<String>)>> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    let ids = MbId::ALL.iter().filter_map(|key| {
+        let values: Vec<String> = if *key == MbId::Recording {
+            tag.unique_file_identifiers()
+                .filter(|u| u.owner_identifier == UFID_OWNER)
+                .map(|u| String::from_utf8_lossy(&u.identifier).to_string())
+                .collect()
+        } else {
+            tag.extended_texts()
+                .filter(|t| t.description == key.description())
+                .flat_map(|t| t.value.split('\0').map(str::to_string))
+                .collect()
+        };
+        (!values.is_empty()).then_some((*key, values))
+    });
+    Some(ids.collect())
+}
+fn clear_id3(path: &Path) -> Result<(), Error> {
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => return Ok(()),
+        Err(e) => bail!(e),
+    };
+    for key in MbId::ALL {
+        if key == MbId::Recording {
+            tag.remove_unique_file_identifier_by_owner_identifier(UFID_OWNER);
+        } else {
+            tag.remove_extended_text(Some(key.description()), None);
+        }
+    }
+    tag.write_to_path(path, Version::Id3v24)?;
+    Ok(())
+}
+fn read_vorbis(path: &Path) -> Option<Vec<(MbId, Vec<String>)>> {
+    let tag = metaflac::Tag::read_from_path(path).ok()?;
+    let ids = MbId::ALL.iter().filter_map(|key| {
+        let values: Vec<String> = tag
+            .get_vorbis(key.vorbis_key())?
+            .map(str::to_string)
+            .collect();
+        (!values.is_empty()).then_some((*key, values))
+    });
+    Some(ids.collect())
+}
+fn clear_vorbis(path: &Path) -> Result<(), Error> {
+    let mut tag = metaflac::Tag::read_from_path(path)?;
+    for key in MbId::ALL {
+        tag.remove_vorbis(key.vorbis_key());
+    }
+    tag.save()?;
+    Ok(())
+}
+fn read_mp4(path: &Path) -> Option<Vec<(MbId, Vec<String>)>> {
+    let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+    let ids = MbId::ALL.iter().filter_map(|key| {
+        let values: Vec<String> = tag
+            .strings_of(&FreeformIdent::new(MP4_MEAN, key.description()))
+            .map(str::to_string)
+            .collect();
+        (!values.is_empty()).then_some((*key, values))
+    });
+    Some(ids.collect())
+}
+fn clear_mp4(path: &Path) -> Result<(), Error> {
+    let mut tag = mp4ameta::Tag::read_from_path(path)?;
+    for key in MbId::ALL {
+        tag.remove_data_of(&FreeformIdent::new(MP4_MEAN, key.description()));
+    }
+    tag.write_to_path(path)?;
+    Ok(())
+}
+fn write_vorbis(path: &Path, ids: &[(MbId, Vec<String>)]) -> Result<(), Error> {
+    let mut tag = metaflac::Tag::read_from_path(path)?;
+    for (key, values) in ids {
+        tag.set_vorbis(key.vorbis_key(), values.clone());
+    }
+    tag.save()?;
+    Ok(())
+}
+fn write_mp4(path: &Path, ids: &[(MbId, Vec<String>)]) -> Result<(), Error> {
+    let mut tag = mp4ameta::Tag::read_from_path(path)?;
+    for (key, values) in ids {
+        tag.set_all_data(
+            FreeformIdent::new(MP4_MEAN, key.description()),
+            values.iter().map(|v| Data::Utf8(v.clone())),
+        );
+    }
+    tag.write_to_path(path)?;
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::parser::release_tags::{MbId, ReleaseInfo, SavedIds, write_ids};
+
+    #[test]
+    fn id3_ids() {
+        let path = env::temp_dir().join(format!("mass_coverart_ids_{}.mp3", std::process::id()));
+        fs::write(&path, []).unwrap();
+        let before = SavedIds::read(&path);
+        let release = ReleaseInfo {
+            release_id: "b1".to_string(),
+            release_group_id: Some("g1".to_string()),
+            recording_id: Some("r1".to_string()),
+            artist_ids: vec!["a1".to_string(), "a2".to_string()],
+            ..Default::default()
+        };
+        write_ids(&path, &release.ids()).unwrap();
+        // writing again replaces frames instead of adding more
+        write_ids(&path, &release.ids()).unwrap();
+
+        let tag = id3::Tag::read_from_path(&path).unwrap();
+        let saved = SavedIds::read(&path);
+        before.restore(&path).unwrap();
+        let restored = id3::Tag::read_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved, SavedIds(release.ids()));
+        assert_eq!(restored.extended_texts().count(), 0);
+        assert_eq!(restored.unique_file_identifiers().count(), 0);
+        let txxx: Vec<_> = tag.extended_texts().collect();
+        assert_eq!(txxx.len(), 3);
+        let value = |key: MbId| {
+            txxx.iter()
+                .find(|t| t.description == key.description())
+                .map(|t| t.value.clone())
+        };
+        assert_eq!(value(MbId::Release), Some("b1".to_string()));
+        assert_eq!(value(MbId::ReleaseGroup), Some("g1".to_string()));
+        assert_eq!(value(MbId::Artist), Some("a1\0a2".to_string()));
+        let ufid: Vec<_> = tag.unique_file_identifiers().collect();
+        assert_eq!(ufid.len(), 1);
+        assert_eq!(ufid[0].identifier, b"r1");
+    }
+}
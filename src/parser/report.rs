@@ -0,0 +1,267 @@
+//! Embedded art problems of a library, found without contacting any source
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Cursor,
+    path::PathBuf,
+};
+
+use anyhow::Error;
+use image::ImageReader;
+use serde::Serialize;
+
+use crate::{
+    app::{
+        album::{AlbumKey, album_key},
+        song::Song,
+    },
+    parser::file_parser::TagData,
+};
+
+/// * `AlbumMismatch`: another track of the same album embeds a different image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    NoArt,
+    LowRes,
+    NotSquare,
+    Format,
+    AlbumMismatch,
+}
+impl Problem {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::NoArt => "no_art",
+            Self::LowRes => "low_res",
+            Self::NotSquare => "not_square",
+            Self::Format => "format",
+            Self::AlbumMismatch => "album_mismatch",
+        }
+    }
+}
+
+/// Embedded cover, only the header is read
+/// * `format`: extension of the guessed format, `unknown` if the bytes are not an image
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ArtInfo {
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Row {
+    pub path: PathBuf,
+    pub art: Option<ArtInfo>,
+    pub problems: Vec<Problem>,
+}
+
+/// Art info and a hash of the image bytes to compare tracks of an album
+fn read_art(tag_data: &TagData) -> Option<(ArtInfo, u64)> {
+    let pic = tag_data.file.album_cover()?;
+    let mut hasher = DefaultHasher::new();
+    pic.data.hash(&mut hasher);
+
+    let reader = ImageReader::new(Cursor::new(pic.data)).with_guessed_format();
+    let format = reader
+        .as_ref()
+        .ok()
+        .and_then(|r| r.format())
+        .map_or("unknown", |f| f.extensions_str()[0])
+        .to_string();
+    let (width, height) = reader
+        .ok()
+        .and_then(|r| r.into_dimensions().ok())
+        .unwrap_or_default();
+    Some((
+        ArtInfo {
+            width,
+            height,
+            format,
+        },
+        hasher.finish(),
+    ))
+}
+/// * `min_size`: smaller side of the art in px
+fn classify_art(art: Option<&ArtInfo>, min_size: u32) -> Vec<Problem> {
+    let Some(art) = art else {
+        return vec![Problem::NoArt];
+    };
+    let mut problems = Vec::new();
+    if art.width.min(art.height) < min_size {
+        problems.push(Problem::LowRes);
+    }
+    if art.width != art.height {
+        problems.push(Problem::NotSquare);
+    }
+    if !matches!(art.format.as_str(), "jpg" | "png") {
+        problems.push(Problem::Format);
+    }
+    problems
+}
+/// Tracks whose art differs from the art most tracks of their album share,
+/// tracks without art are left to `NoArt`
+fn mismatched(keys: &[Option<AlbumKey>], hashes: &[Option<u64>]) -> Vec<bool> {
+    let mut albums: HashMap<&AlbumKey, Vec<usize>> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(key) = key {
+            albums.entry(key).or_default().push(i);
+        }
+    }
+    let mut ret = vec![false; keys.len()];
+    for members in albums.values() {
+        let mut counts: Vec<(u64, usize)> = Vec::new();
+        for hash in members.iter().filter_map(|i| hashes[*i]) {
+            match counts.iter_mut().find(|(h, _)| *h == hash) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((hash, 1)),
+            }
+        }
+        // first one seen wins a tie
+        let Some(common) = counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(h, _)| *h)
+        else {
+            continue;
+        };
+        for i in members {
+            ret[*i] = hashes[*i].is_some_and(|h| h != common);
+        }
+    }
+    ret
+}
+
+pub fn classify(songs: &[Song], min_size: u32) -> Vec<Row> {
+    let arts: Vec<_> = songs.iter().map(|s| read_art(&s.tag_data)).collect();
+    let keys: Vec<_> = songs.iter().map(|s| album_key(&s.tag_data)).collect();
+    let hashes: Vec<_> = arts.iter().map(|a| a.as_ref().map(|(_, h)| *h)).collect();
+    let mismatched = mismatched(&keys, &hashes);
+
+    songs
+        .iter()
+        .zip(arts)
+        .zip(mismatched)
+        .map(|((song, art), mismatch)| {
+            let art = art.map(|(art, _)| art);
+            let mut problems = classify_art(art.as_ref(), min_size);
+            if mismatch {
+                problems.push(Problem::AlbumMismatch);
+            }
+            Row {
+                path: song.tag_data.path.clone(),
+                art,
+                problems,
+            }
+        })
+        .collect()
+}
+/// Keep the songs with at least one problem
+pub fn problematic(songs: Vec<Song>, min_size: u32) -> Vec<Song> {
+    let rows = classify(&songs, min_size);
+    songs
+        .into_iter()
+        .zip(rows)
+        .filter(|(_, row)| !row.problems.is_empty())
+        .map(|(song, _)| song)
+        .collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+/// One line per file, problems are separated by `;`
+pub fn to_csv(rows: &[Row]) -> String {
+    let mut out = String::from("path,width,height,format,problems\n");
+    for row in rows {
+        let (width, height, format) = match &row.art {
+            Some(art) => (
+                art.width.to_string(),
+                art.height.to_string(),
+                art.format.as_str(),
+            ),
+            None => (String::new(), String::new(), ""),
+        };
+        let problems: Vec<_> = row.problems.iter().map(|p| p.to_str()).collect();
+        out.push_str(&format!(
+            "{},{width},{height},{format},{}\n",
+            csv_field(&row.path.to_string_lossy()),
+            problems.join(";")
+        ));
+    }
+    out
+}
+pub fn to_json(rows: &[Row]) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(rows)?)
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::parser::report::{ArtInfo, Problem, Row, classify_art, mismatched, to_csv};
+
+    fn art(width: u32, height: u32, format: &str) -> ArtInfo {
+        ArtInfo {
+            width,
+            height,
+            format: format.to_string(),
+        }
+    }
+
+    #[test]
+    fn art_classes() {
+        use Problem::*;
+        assert_eq!(classify_art(None, 500), vec![NoArt]);
+        assert!(classify_art(Some(&art(1000, 1000, "jpg")), 500).is_empty());
+        assert_eq!(classify_art(Some(&art(300, 300, "png")), 500), vec![LowRes]);
+        assert_eq!(
+            classify_art(Some(&art(1000, 400, "gif")), 500),
+            vec![LowRes, NotSquare, Format]
+        );
+        assert_eq!(classify_art(Some(&art(0, 0, "unknown")), 0), vec![Format]);
+    }
+    #[test]
+    fn album_mismatch() {
+        let key = |a: &str| Some(("artist".to_string(), a.to_string()));
+        let keys = vec![
+            key("a"),
+            key("a"),
+            key("a"),
+            key("a"),
+            key("b"),
+            key("b"),
+            None,
+        ];
+        let hashes = vec![Some(1), Some(2), Some(1), None, Some(3), Some(4), Some(5)];
+        assert_eq!(
+            mismatched(&keys, &hashes),
+            vec![false, true, false, false, false, true, false]
+        );
+    }
+    #[test]
+    fn csv_escapes_path() {
+        let rows = vec![
+            Row {
+                path: PathBuf::from("/music/a, b.mp3"),
+                art: None,
+                problems: vec![Problem::NoArt],
+            },
+            Row {
+                path: PathBuf::from("/music/c.mp3"),
+                art: Some(art(300, 200, "png")),
+                problems: vec![Problem::LowRes, Problem::NotSquare],
+            },
+        ];
+        assert_eq!(
+            to_csv(&rows),
+            "path,width,height,format,problems\n\
+             \"/music/a, b.mp3\",,,,no_art\n\
+             /music/c.mp3,300,200,png,low_res;not_square\n"
+        );
+    }
+}